@@ -52,7 +52,7 @@ fn prepare_rcon(config: &mut Config) {
     }
 
     // Must configure RCON password with no randomization
-    if config.server.address.port() == config.rcon.port {
+    if config.server.address().port() == config.rcon.port {
         quit_error_msg(
             "RCON port cannot be the same as the server",
             ErrorHintsBuilder::default()
@@ -131,14 +131,14 @@ fn rewrite_server_properties(config: &Config) {
     // Build list of changes
     #[allow(unused_mut)]
     let mut changes = HashMap::from([
-        ("server-ip", config.server.address.ip().to_string()),
-        ("server-port", config.server.address.port().to_string()),
+        ("server-ip", config.server.address().ip().to_string()),
+        ("server-port", config.server.address().port().to_string()),
         ("enable-status", "true".into()),
-        ("query.port", config.server.address.port().to_string()),
+        ("query.port", config.server.address().port().to_string()),
     ]);
 
     // If connecting to server over non-loopback address, disable proxy blocking
-    if !config.server.address.ip().is_loopback() {
+    if !config.server.address().ip().is_loopback() {
         changes.extend([("prevent-proxy-connections", "false".into())]);
     }
 
@@ -1,15 +1,18 @@
+use std::collections::HashMap;
 use std::fs;
 use std::io;
 use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::time::Duration;
 
+use chrono::{Datelike, NaiveDate};
 use clap::ArgMatches;
 use serde::Deserialize;
 use version_compare::Cmp;
 
 use crate::proto;
 use crate::util::error::{quit_error, quit_error_msg, ErrorHintsBuilder};
-use crate::util::serde::to_socket_addrs;
+use crate::util::serde::{to_all_socket_addrs, to_socket_addrs};
 
 /// Default configuration file location.
 pub const CONFIG_FILE: &str = "lazymc.toml";
@@ -17,6 +20,9 @@ pub const CONFIG_FILE: &str = "lazymc.toml";
 /// Configuration version user should be using, or warning will be shown.
 const CONFIG_VERSION: &str = "0.2.8";
 
+/// Valid server process niceness range.
+const NICE_RANGE: std::ops::RangeInclusive<i32> = -20..=19;
+
 /// Load config from file, based on CLI arguments.
 ///
 /// Quits with an error message on failure.
@@ -92,6 +98,14 @@ pub struct Config {
     #[serde(default)]
     pub lockout: Lockout,
 
+    /// Bans configuration.
+    #[serde(default)]
+    pub bans: Bans,
+
+    /// Honeypot feature.
+    #[serde(default)]
+    pub honeypot: Honeypot,
+
     /// RCON configuration.
     #[serde(default)]
     pub rcon: Rcon,
@@ -126,6 +140,21 @@ impl Config {
         }
         config.path.replace(path);
 
+        // Validate MOTD theme date ranges
+        for theme in &config.motd.themes {
+            theme.validate()?;
+        }
+
+        // Validate server niceness range
+        if let Some(nice) = config.server.nice {
+            if !NICE_RANGE.contains(&nice) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid server nice value {nice}, must be from -20 to 19"),
+                ));
+            }
+        }
+
         Ok(config)
     }
 }
@@ -143,6 +172,9 @@ pub struct Public {
 
     /// Minecraft protocol version hint.
     pub protocol: u32,
+
+    /// Behavior on status requests from clients with an unknown protocol version.
+    pub unknown_protocol_behavior: UnknownProtocolBehavior,
 }
 
 impl Default for Public {
@@ -151,10 +183,28 @@ impl Default for Public {
             address: "0.0.0.0:25565".parse().unwrap(),
             version: proto::PROTO_DEFAULT_VERSION.to_string(),
             protocol: proto::PROTO_DEFAULT_PROTOCOL,
+            unknown_protocol_behavior: Default::default(),
         }
     }
 }
 
+/// Behavior on status requests from clients with an unknown protocol version.
+#[derive(Debug, Deserialize, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum UnknownProtocolBehavior {
+    /// Respond with status for configured or last known server version.
+    Configured,
+
+    /// Disconnect without responding.
+    Disconnect,
+}
+
+impl Default for UnknownProtocolBehavior {
+    fn default() -> Self {
+        Self::Configured
+    }
+}
+
 /// Server configuration.
 #[derive(Debug, Deserialize)]
 pub struct Server {
@@ -167,26 +217,49 @@ pub struct Server {
     /// Start command.
     pub command: String,
 
-    /// Server address.
+    /// Server addresses, all addresses the configured server address resolves to.
+    ///
+    /// Never empty. Use `address()` for the primary address.
     #[serde(
-        deserialize_with = "to_socket_addrs",
-        default = "server_address_default"
+        rename = "address",
+        deserialize_with = "to_all_socket_addrs",
+        default = "server_addresses_default"
     )]
-    pub address: SocketAddr,
+    pub addresses: Vec<SocketAddr>,
+
+    /// Address selection policy if server address resolves to multiple IPs.
+    #[serde(default)]
+    pub address_selection: AddressSelection,
 
     /// Freeze the server process instead of restarting it when no players online, making it start up faster.
     /// Only works on Unix (Linux or MacOS)
     #[serde(default = "bool_true")]
     pub freeze_process: bool,
 
+    /// Sleep stages, to stop a frozen server process after some time.
+    #[serde(default)]
+    pub sleep_stages: SleepStages,
+
     /// Immediately wake server when starting lazymc.
     #[serde(default)]
     pub wake_on_start: bool,
 
     /// Immediately wake server after crash.
-    #[serde(default)]
+    #[serde(default, alias = "restart_on_crash")]
     pub wake_on_crash: bool,
 
+    /// Maximum number of consecutive crashes to restart server for, 0 for unlimited.
+    #[serde(default)]
+    pub crash_restart_limit: u32,
+
+    /// Delay in seconds before restarting crashed server, doubled on each consecutive crash.
+    #[serde(default)]
+    pub crash_restart_backoff: u32,
+
+    /// Adopt server started outside of lazymc, treat it as started.
+    #[serde(default = "bool_true")]
+    pub adopt_external: bool,
+
     /// Probe required server details when starting lazymc, wakes server on start.
     #[serde(default)]
     pub probe_on_start: bool,
@@ -203,6 +276,37 @@ pub struct Server {
     #[serde(default = "u32_150")]
     pub stop_timeout: u32,
 
+    /// Time in seconds after the server process quit before it may be started again.
+    #[serde(default)]
+    pub restart_cooldown: u32,
+
+    /// Time in seconds after a client IP woke the server before it may wake it again.
+    #[serde(default)]
+    pub per_ip_wake_cooldown: u32,
+
+    /// Ignore online players if all are proxied and sent no data for number of seconds, they then
+    /// don't keep the server awake past `time.sleep_after`.
+    #[serde(default)]
+    pub proxy_byte_idle: u32,
+
+    /// Send a warm ping login every number of seconds while online without players, 0 to disable.
+    #[serde(default)]
+    pub warm_ping_interval: u32,
+
+    /// What counts as activity, resetting the sleep timer.
+    #[serde(default = "activity_sources_default")]
+    pub activity_sources: Vec<ActivitySource>,
+
+    /// Niceness to run the server process with, from -20 to 19.
+    /// Only works on Unix (Linux or MacOS)
+    #[serde(default)]
+    pub nice: Option<i32>,
+
+    /// Cgroup to move the server process into.
+    /// Only works on Linux
+    #[serde(default)]
+    pub cgroup: Option<String>,
+
     /// To wake server, user must be in server whitelist if enabled on server.
     #[serde(default = "bool_true")]
     pub wake_whitelist: bool,
@@ -218,6 +322,24 @@ pub struct Server {
     /// Add HAProxy v2 header to proxied connections.
     #[serde(default)]
     pub send_proxy_v2: bool,
+
+    /// Other servers to proxy clients to by the hostname they connect with.
+    ///
+    /// Private because you should use `Server::route()` instead.
+    #[serde(default)]
+    routes: HashMap<String, SocketAddr>,
+
+    /// Timeout in seconds for connecting to the server, 0 to use OS default.
+    #[serde(default)]
+    pub connect_timeout: u32,
+
+    /// Forward server process output to lazymc log.
+    #[serde(default)]
+    pub forward_output: bool,
+
+    /// Log level to forward server process output at.
+    #[serde(default)]
+    pub forward_output_level: OutputLevel,
 }
 
 impl Server {
@@ -231,6 +353,124 @@ impl Server {
             None => config.server.directory.clone(),
         }
     }
+
+    /// Whether client connections count as activity, see `activity_sources`.
+    pub fn has_client_activity(&self) -> bool {
+        self.activity_sources
+            .iter()
+            .any(|source| *source != ActivitySource::Players)
+    }
+
+    /// Whether any hostname routes to other servers are configured.
+    pub fn has_routes(&self) -> bool {
+        !self.routes.is_empty()
+    }
+
+    /// Get the address of the other server to route the given hostname to, if any.
+    ///
+    /// Hostnames are compared case-insensitively.
+    pub fn route(&self, hostname: &str) -> Option<SocketAddr> {
+        self.routes
+            .iter()
+            .find(|(host, _)| host.eq_ignore_ascii_case(hostname))
+            .map(|(_, addr)| *addr)
+    }
+
+    /// Get the primary server address, the first address it resolves to.
+    pub fn address(&self) -> SocketAddr {
+        self.addresses[0]
+    }
+
+    /// Get the server connect timeout, `None` if not set.
+    pub fn connect_timeout(&self) -> Option<Duration> {
+        match self.connect_timeout {
+            0 => None,
+            secs => Some(Duration::from_secs(secs as u64)),
+        }
+    }
+}
+
+/// Sleep stages.
+///
+/// A sleeping server is frozen first, and is stopped if it keeps sleeping.
+#[derive(Debug, Deserialize, Default)]
+#[serde(default)]
+pub struct SleepStages {
+    /// Stop frozen server process after number of seconds, 0 to keep it frozen.
+    pub freeze_for: u32,
+}
+
+/// Server address selection policy.
+#[derive(Debug, Deserialize, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum AddressSelection {
+    /// Always use the first resolved address.
+    First,
+
+    /// Rotate between resolved addresses for each connection.
+    RoundRobin,
+
+    /// Use first reachable address, try the next one on connection failure.
+    Failover,
+}
+
+impl Default for AddressSelection {
+    fn default() -> Self {
+        Self::First
+    }
+}
+
+/// Source of activity resetting the sleep timer.
+#[derive(Debug, Deserialize, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ActivitySource {
+    /// Players online as reported by server status.
+    Players,
+
+    /// Client logging in through lazymc while the server is online.
+    Login,
+
+    /// Client requesting server status, such as the server list.
+    Status,
+}
+
+/// Log level for forwarded server output.
+#[derive(Debug, Deserialize, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputLevel {
+    /// Error level.
+    Error,
+
+    /// Warning level.
+    Warn,
+
+    /// Info level.
+    Info,
+
+    /// Debug level.
+    Debug,
+
+    /// Trace level.
+    Trace,
+}
+
+impl OutputLevel {
+    /// Get log level.
+    pub fn level(self) -> log::Level {
+        match self {
+            Self::Error => log::Level::Error,
+            Self::Warn => log::Level::Warn,
+            Self::Info => log::Level::Info,
+            Self::Debug => log::Level::Debug,
+            Self::Trace => log::Level::Trace,
+        }
+    }
+}
+
+impl Default for OutputLevel {
+    fn default() -> Self {
+        Self::Info
+    }
 }
 
 /// Time configuration.
@@ -269,6 +509,47 @@ pub struct Motd {
 
     /// Use MOTD from Minecraft server once known.
     pub from_server: bool,
+
+    /// MOTD when sleeping and using MOTD from Minecraft server, but it isn't known yet.
+    pub from_server_fallback: Option<String>,
+
+    /// Relay live status from Minecraft server when online, instead of proxying status connections.
+    ///
+    /// Relays the server response as is, uses cached status if it fails.
+    pub proxy_when_running: bool,
+
+    /// Player sample lines when server is starting, one is shown on each status request.
+    pub starting_sample: Vec<String>,
+
+    /// Format of configured MOTDs.
+    pub format: MotdFormat,
+
+    /// MOTD when server is online without players.
+    pub empty: Option<String>,
+
+    /// Favicon when server is online without players, path to PNG image.
+    pub favicon_empty: Option<PathBuf>,
+
+    /// MOTD when server is sleeping with a frozen process.
+    pub frozen: Option<String>,
+
+    /// Maximum status response size in UTF-16 code units, 0 for unlimited.
+    pub max_status_size: u32,
+
+    /// Date ranged MOTD and favicon themes, first matching theme is used.
+    pub themes: Vec<MotdTheme>,
+}
+
+impl Motd {
+    /// Whether a MOTD or favicon is set for when the server is online without players.
+    pub fn has_empty(&self) -> bool {
+        self.empty.is_some() || self.favicon_empty.is_some()
+    }
+
+    /// Get first theme matching the given date.
+    pub fn theme(&self, date: NaiveDate) -> Option<&MotdTheme> {
+        self.themes.iter().find(|theme| theme.matches(date))
+    }
 }
 
 impl Default for Motd {
@@ -278,8 +559,99 @@ impl Default for Motd {
             starting: "§2☻ Server is starting...\n§7⌛ Please wait...".into(),
             stopping: "☠ Server going to sleep...\n⌛ Please wait...".into(),
             from_server: false,
+            from_server_fallback: None,
+            proxy_when_running: false,
+            starting_sample: vec![],
+            format: Default::default(),
+            empty: None,
+            favicon_empty: None,
+            frozen: None,
+            max_status_size: 32767,
+            themes: vec![],
+        }
+    }
+}
+
+/// Date ranged MOTD and favicon theme, such as for holidays.
+#[derive(Debug, Deserialize)]
+pub struct MotdTheme {
+    /// First day of theme, as `MM-DD`.
+    pub from: String,
+
+    /// Last day of theme, as `MM-DD`, inclusive. May be before `from` to wrap around new year.
+    pub to: String,
+
+    /// MOTD when server is sleeping.
+    pub sleeping: Option<String>,
+
+    /// MOTD when server is starting.
+    pub starting: Option<String>,
+
+    /// MOTD when server is stopping.
+    pub stopping: Option<String>,
+
+    /// Favicon, path to PNG image.
+    pub favicon: Option<PathBuf>,
+}
+
+impl MotdTheme {
+    /// Whether the theme is active on the given date.
+    pub fn matches(&self, date: NaiveDate) -> bool {
+        let (from, to) = match (parse_month_day(&self.from), parse_month_day(&self.to)) {
+            (Some(from), Some(to)) => (from, to),
+            _ => return false,
+        };
+        let day = (date.month(), date.day());
+
+        if from <= to {
+            from <= day && day <= to
+        } else {
+            day >= from || day <= to
         }
     }
+
+    /// Validate theme date range.
+    fn validate(&self) -> Result<(), io::Error> {
+        for date in [&self.from, &self.to] {
+            if parse_month_day(date).is_none() {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid MOTD theme date '{date}', must be MM-DD"),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parse `MM-DD` date into month and day.
+fn parse_month_day(date: &str) -> Option<(u32, u32)> {
+    let (month, day) = date.split_once('-')?;
+    let (month, day) = (month.parse().ok()?, day.parse().ok()?);
+
+    // Validate using leap year, to allow February 29th
+    NaiveDate::from_ymd_opt(2000, month, day)?;
+    Some((month, day))
+}
+
+/// MOTD format.
+#[derive(Debug, Deserialize, Copy, Clone, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MotdFormat {
+    /// Text with legacy `§` formatting codes.
+    Legacy,
+
+    /// MiniMessage tags, such as `<red>`.
+    MiniMessage,
+
+    /// Plain text, formatting codes are removed.
+    Plain,
+}
+
+impl Default for MotdFormat {
+    fn default() -> Self {
+        Self::Legacy
+    }
 }
 
 /// Join method types.
@@ -321,6 +693,26 @@ pub struct Join {
     /// Join lobby configuration.
     #[serde(default)]
     pub lobby: JoinLobby,
+
+    /// Join queue configuration.
+    #[serde(default)]
+    pub queue: JoinQueue,
+
+    /// Maximum number of clients waiting for server to start, 0 for unlimited.
+    #[serde(default)]
+    pub max_waiting: u32,
+
+    /// Maximum number of logins per minute passed to the server, 0 for unlimited.
+    #[serde(default)]
+    pub max_logins_per_minute: u32,
+
+    /// Stop starting server if all clients waiting for it disconnected.
+    #[serde(default)]
+    pub cancel_abandoned_start: bool,
+
+    /// Kick clients sending a login start that doesn't match their handshake protocol version.
+    #[serde(default)]
+    pub strict_protocol_consistency: bool,
 }
 
 impl Default for Join {
@@ -331,6 +723,11 @@ impl Default for Join {
             hold: Default::default(),
             forward: Default::default(),
             lobby: Default::default(),
+            queue: Default::default(),
+            max_waiting: 0,
+            max_logins_per_minute: 0,
+            cancel_abandoned_start: false,
+            strict_protocol_consistency: false,
         }
     }
 }
@@ -344,6 +741,9 @@ pub struct JoinKick {
 
     /// Kick message when server is stopping.
     pub stopping: String,
+
+    /// Kick message when server is restarting after a crash.
+    pub crashed: String,
 }
 
 impl Default for JoinKick {
@@ -351,6 +751,7 @@ impl Default for JoinKick {
         Self {
             starting: "Server is starting... §c♥§r\n\nThis may take some time.\n\nPlease try to reconnect in a minute.".into(),
             stopping: "Server is going to sleep... §7☠§r\n\nPlease try to reconnect in a minute to wake it again.".into(),
+            crashed: "Server crashed, restarting... §c♥§r\n\nPlease try to reconnect in a minute.".into(),
         }
     }
 }
@@ -414,6 +815,26 @@ impl Default for JoinLobby {
     }
 }
 
+/// Join queue configuration.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct JoinQueue {
+    /// Queue clients in the lobby if the server is full or too many clients are waiting.
+    pub enabled: bool,
+
+    /// Message banner in lobby shown to queued client, `{position}` is replaced with its position.
+    pub message: String,
+}
+
+impl Default for JoinQueue {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            message: "§6Server is full\n§7You are in queue at position {position}".into(),
+        }
+    }
+}
+
 /// Lockout configuration.
 #[derive(Debug, Deserialize)]
 #[serde(default)]
@@ -434,6 +855,36 @@ impl Default for Lockout {
     }
 }
 
+/// Bans configuration.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Bans {
+    /// Kick players listed in banned-players.json by UUID or username.
+    ///
+    /// The UUID and username are sent by the client and are not verified.
+    pub by_uuid: bool,
+}
+
+/// Honeypot configuration.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Honeypot {
+    /// Enable to keep all joining clients in a fake lobby, never waking the server.
+    pub enabled: bool,
+
+    /// Message banner in fake lobby shown to client.
+    pub message: String,
+}
+
+impl Default for Honeypot {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            message: "§2Server is starting\n§7⌛ Please wait...".into(),
+        }
+    }
+}
+
 /// RCON configuration.
 #[derive(Debug, Deserialize)]
 #[serde(default)]
@@ -472,12 +923,56 @@ impl Default for Rcon {
 pub struct Advanced {
     /// Rewrite server.properties.
     pub rewrite_server_properties: bool,
+
+    /// Log summary of protocol errors every number of seconds, 0 to disable.
+    pub metrics_log_interval: u32,
+
+    /// Serve metrics over HTTP in Prometheus text format on this address, disabled if not set.
+    pub metrics_address: Option<SocketAddr>,
+
+    /// Close proxied connections if client sends nothing for number of seconds, 0 to disable.
+    pub proxy_idle_timeout: u32,
+
+    /// Stop server on SIGINT (Ctrl-C), or quit and leave it running.
+    /// SIGTERM always stops the server.
+    pub sigint_stops_backend: bool,
+
+    /// Show hostnames of client IPs in logs through reverse DNS.
+    pub reverse_dns: bool,
+
+    /// Drop idle status connections when the server starts.
+    pub drop_idle_on_start: bool,
+
+    /// Close connections if client sends nothing for number of seconds after connecting, 0 to
+    /// disable.
+    pub idle_connect_timeout: u32,
+
+    /// Warn if handing off a joining client to the server takes number of milliseconds, 0 to
+    /// disable.
+    pub slow_handoff_threshold: u32,
+
+    /// Log single line summary of each connection.
+    pub connection_summary_log: bool,
+
+    /// CPU cores to pin lazymc to, empty to not pin.
+    /// Only works on Linux
+    pub cpu_affinity: Vec<usize>,
 }
 
 impl Default for Advanced {
     fn default() -> Self {
         Self {
             rewrite_server_properties: true,
+            metrics_log_interval: 0,
+            metrics_address: None,
+            proxy_idle_timeout: 0,
+            sigint_stops_backend: true,
+            reverse_dns: false,
+            drop_idle_on_start: false,
+            idle_connect_timeout: 0,
+            slow_handoff_threshold: 0,
+            connection_summary_log: false,
+            cpu_affinity: vec![],
         }
     }
 }
@@ -494,8 +989,12 @@ fn option_pathbuf_dot() -> Option<PathBuf> {
     Some(".".into())
 }
 
-fn server_address_default() -> SocketAddr {
-    "127.0.0.1:25566".parse().unwrap()
+fn server_addresses_default() -> Vec<SocketAddr> {
+    vec!["127.0.0.1:25566".parse().unwrap()]
+}
+
+fn activity_sources_default() -> Vec<ActivitySource> {
+    vec![ActivitySource::Players, ActivitySource::Login]
 }
 
 fn u32_300() -> u32 {
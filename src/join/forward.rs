@@ -4,7 +4,7 @@ use bytes::BytesMut;
 use tokio::net::TcpStream;
 
 use crate::config::*;
-use crate::proxy::ProxyHeader;
+use crate::proxy::{ProxyHeader, ProxyOptions};
 use crate::service;
 
 use super::MethodResult;
@@ -22,8 +22,9 @@ pub async fn occupy(
     service::server::route_proxy_address_queue(
         inbound,
         ProxyHeader::Proxy.not_none(config.join.forward.send_proxy_v2),
-        config.join.forward.address,
+        vec![config.join.forward.address],
         inbound_history.clone(),
+        ProxyOptions::default(),
     );
 
     // TODO: do not consume, continue on proxy connect failure
@@ -7,6 +7,7 @@ use tokio::net::TcpStream;
 use tokio::time;
 
 use crate::config::*;
+use crate::net;
 use crate::server::{Server, State};
 use crate::service;
 
@@ -26,10 +27,19 @@ pub async fn occupy(
         return Ok(MethodResult::Continue(inbound));
     }
 
-    // Start holding, consume client
-    if hold(&config, &server).await? {
-        service::server::route_proxy_queue(inbound, config, inbound_history.clone());
-        return Ok(MethodResult::Consumed);
+    // Start holding, consume client, stop holding if client disconnects
+    tokio::select! {
+        held = hold(&config, &server) => {
+            if held? {
+                service::server::route_proxy_queue(inbound, config, &server, inbound_history.clone(), true);
+                return Ok(MethodResult::Consumed);
+            }
+        }
+        _ = net::wait_closed(&inbound) => {
+            debug!(target: "lazymc", "Held client disconnected before server was ready");
+            server.abandon_start(&config).await;
+            return Ok(MethodResult::Consumed);
+        }
     }
 
     Ok(MethodResult::Continue(inbound))
@@ -19,6 +19,9 @@ pub async fn occupy(
 
     // Select message and kick
     let msg = match server.state() {
+        server::State::Starting | server::State::Stopped if server.is_crash_restarting() => {
+            &config.join.kick.crashed
+        }
         server::State::Starting | server::State::Stopped | server::State::Started => {
             &config.join.kick.starting
         }
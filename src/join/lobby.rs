@@ -11,6 +11,8 @@ use crate::server::Server;
 use super::MethodResult;
 
 /// Lobby the client.
+///
+/// If `queued` is set, the client waits in the join queue in the lobby first.
 pub async fn occupy(
     client: &Client,
     client_info: ClientInfo,
@@ -18,6 +20,7 @@ pub async fn occupy(
     server: Arc<Server>,
     inbound: TcpStream,
     inbound_queue: BytesMut,
+    queued: bool,
 ) -> Result<MethodResult, ()> {
     trace!(target: "lazymc", "Using lobby method to occupy joining client");
 
@@ -28,7 +31,16 @@ pub async fn occupy(
     }
 
     // Start lobby
-    lobby::serve(client, client_info, inbound, config, server, inbound_queue).await?;
+    lobby::serve(
+        client,
+        client_info,
+        inbound,
+        config,
+        server,
+        inbound_queue,
+        queued,
+    )
+    .await?;
 
     // TODO: do not consume client here, allow other join method on fail
 
@@ -5,6 +5,7 @@ use tokio::net::TcpStream;
 
 use crate::config::*;
 use crate::net;
+use crate::proto::action;
 use crate::proto::client::{Client, ClientInfo, ClientState};
 use crate::server::Server;
 
@@ -13,6 +14,12 @@ pub mod hold;
 pub mod kick;
 #[cfg(feature = "lobby")]
 pub mod lobby;
+#[cfg(feature = "lobby")]
+pub mod queue;
+
+/// Kick message when too many clients are waiting for the server to start.
+const MAX_WAITING_MESSAGE: &str =
+    "Server is starting, too many players waiting.\n\nPlease try to reconnect in a minute.";
 
 /// A result returned by a join occupy method.
 pub enum MethodResult {
@@ -42,6 +49,34 @@ pub async fn occupy(
         "when occupying client, it should be in login state"
     );
 
+    // Kick if too many clients are waiting already, guard unregisters client when done
+    let _waiting = match server.try_wait(&config) {
+        Some(guard) => guard,
+        None if config.join.queue.enabled => {
+            info!(target: "lazymc", "Too many players waiting for server to start, queueing player");
+            if let MethodResult::Continue(mut inbound) = occupy_queue(
+                &client,
+                client_info,
+                config.clone(),
+                server.clone(),
+                inbound,
+                login_queue,
+            )
+            .await?
+            {
+                action::kick(&client, MAX_WAITING_MESSAGE, &mut inbound.split().1).await?;
+                net::close_tcp_stream(inbound).await.map_err(|_| ())?;
+            }
+            return Ok(());
+        }
+        None => {
+            info!(target: "lazymc", "Kicked player because too many players are waiting for server to start");
+            action::kick(&client, MAX_WAITING_MESSAGE, &mut inbound.split().1).await?;
+            net::close_tcp_stream(inbound).await.map_err(|_| ())?;
+            return Ok(());
+        }
+    };
+
     // Go through all configured join methods
     for method in &config.join.methods {
         // Invoke method, take result
@@ -75,6 +110,7 @@ pub async fn occupy(
                     server.clone(),
                     inbound,
                     login_queue.clone(),
+                    false,
                 )
                 .await?
             }
@@ -104,3 +140,35 @@ pub async fn occupy(
 
     Ok(())
 }
+
+/// Queue client in the lobby until a slot is free, then join the server.
+///
+/// Continues if the lobby can't be used.
+pub async fn occupy_queue(
+    #[allow(unused_variables)] client: &Client,
+    #[allow(unused_variables)] client_info: ClientInfo,
+    #[allow(unused_variables)] config: Arc<Config>,
+    #[allow(unused_variables)] server: Arc<Server>,
+    inbound: TcpStream,
+    #[allow(unused_variables)] login_queue: BytesMut,
+) -> Result<MethodResult, ()> {
+    #[cfg(feature = "lobby")]
+    {
+        lobby::occupy(
+            client,
+            client_info,
+            config,
+            server,
+            inbound,
+            login_queue,
+            true,
+        )
+        .await
+    }
+
+    #[cfg(not(feature = "lobby"))]
+    {
+        error!(target: "lazymc", "Join queue not supported in this lazymc build, requires lobby");
+        Ok(MethodResult::Continue(inbound))
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::config::*;
+use crate::server::{Server, State, WaitingGuard};
+
+/// Minimum time between releasing queued clients to the online server.
+///
+/// The server status only reflects a released client once it joined, this gives it time to do so
+/// before releasing the next.
+const RELEASE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Queue of joining clients waiting for a free slot, in join order.
+#[derive(Debug, Default)]
+pub struct Queue {
+    state: Mutex<QueueState>,
+}
+
+/// Queued clients and release state.
+#[derive(Debug, Default)]
+struct QueueState {
+    /// Tickets of queued clients, first in queue at the front.
+    tickets: VecDeque<u64>,
+
+    /// ID of next ticket.
+    next_id: u64,
+
+    /// Time a client was last released to the online server.
+    last_release: Option<Instant>,
+}
+
+impl Queue {
+    /// Add client to the back of the queue, until the returned ticket is dropped.
+    pub fn join(&self) -> Ticket<'_> {
+        let mut state = self.state.lock().unwrap();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.tickets.push_back(id);
+        Ticket { queue: self, id }
+    }
+
+    /// Get number of queued clients.
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().tickets.len()
+    }
+
+    /// Whether no clients are queued.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Mark a client released to the online server, if not done in the given interval.
+    fn try_release(&self, interval: Duration) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if state
+            .last_release
+            .map(|at| at.elapsed() < interval)
+            .unwrap_or(false)
+        {
+            return false;
+        }
+        state.last_release.replace(Instant::now());
+        true
+    }
+}
+
+/// Ticket of a queued client, removes client from queue when dropped.
+pub struct Ticket<'a> {
+    queue: &'a Queue,
+    id: u64,
+}
+
+impl Ticket<'_> {
+    /// Get position in queue, starting at 1.
+    pub fn position(&self) -> usize {
+        self.queue
+            .state
+            .lock()
+            .unwrap()
+            .tickets
+            .iter()
+            .position(|id| *id == self.id)
+            .map(|i| i + 1)
+            .unwrap_or(0)
+    }
+}
+
+impl Drop for Ticket<'_> {
+    fn drop(&mut self) {
+        self.queue
+            .state
+            .lock()
+            .unwrap()
+            .tickets
+            .retain(|id| *id != self.id);
+    }
+}
+
+/// A free slot taken by the first queued client.
+pub enum Slot<'a> {
+    /// Slot to wait for the server to start, see `Server::try_wait`.
+    Waiting(WaitingGuard<'a>),
+
+    /// Slot on the online server.
+    Online,
+}
+
+/// Check whether joining client must be queued because the online server is full.
+///
+/// Also queues if others are queued already, to keep join order.
+pub async fn should_queue(config: &Config, server: &Server) -> bool {
+    config.join.queue.enabled && (!server.queue.is_empty() || server.is_full().await)
+}
+
+/// Take a free slot for the client with the given ticket, if it is first in queue.
+///
+/// Clients are released to the online server one at a time while it isn't full. While the
+/// server starts, they are released when fewer than `join.max_waiting` clients are waiting.
+pub async fn take_slot<'a>(
+    server: &'a Server,
+    config: &Config,
+    ticket: &Ticket<'_>,
+) -> Option<Slot<'a>> {
+    if ticket.position() != 1 {
+        return None;
+    }
+
+    match server.state() {
+        State::Started => {
+            let free = !server.is_full().await && server.queue.try_release(RELEASE_INTERVAL);
+            free.then_some(Slot::Online)
+        }
+        State::Starting | State::Stopping | State::Stopped => {
+            server.try_wait(config).map(Slot::Waiting)
+        }
+    }
+}
+
+/// Format queue message for the given position.
+pub fn message(config: &Config, position: usize) -> String {
+    config
+        .join
+        .queue
+        .message
+        .replace("{position}", &position.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use minecraft_protocol::data::chat::{Message, Payload};
+    use minecraft_protocol::data::server_status::{OnlinePlayers, ServerStatus, ServerVersion};
+
+    fn online_status(players: u32) -> ServerStatus {
+        ServerStatus {
+            version: ServerVersion {
+                name: "1.17.1".into(),
+                protocol: 756,
+            },
+            description: Message::new(Payload::text("A Minecraft Server")),
+            players: OnlinePlayers {
+                online: players,
+                max: 20,
+                sample: vec![],
+            },
+            favicon: None,
+        }
+    }
+
+    #[test]
+    fn positions() {
+        let queue = Queue::default();
+        assert!(queue.is_empty());
+
+        let first = queue.join();
+        let second = queue.join();
+        let third = queue.join();
+        assert_eq!(queue.len(), 3);
+        assert_eq!(first.position(), 1);
+        assert_eq!(second.position(), 2);
+        assert_eq!(third.position(), 3);
+
+        // Client leaving the queue moves others up
+        drop(second);
+        assert_eq!(first.position(), 1);
+        assert_eq!(third.position(), 2);
+
+        // Released in order
+        drop(first);
+        assert_eq!(third.position(), 1);
+        let fourth = queue.join();
+        assert_eq!(fourth.position(), 2);
+
+        drop((third, fourth));
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn release_interval() {
+        let queue = Queue::default();
+        assert!(queue.try_release(RELEASE_INTERVAL));
+        assert!(!queue.try_release(RELEASE_INTERVAL));
+        assert!(queue.try_release(Duration::ZERO));
+    }
+
+    #[tokio::test]
+    async fn take_slot_first_waiting() {
+        let mut config: Config = toml::from_str("[server]\ncommand = \"true\"").unwrap();
+        config.join.max_waiting = 1;
+        let server = Server::default();
+
+        let first = server.queue.join();
+        let second = server.queue.join();
+        assert!(take_slot(&server, &config, &second).await.is_none());
+
+        // First takes the only waiting slot
+        let slot = take_slot(&server, &config, &first).await;
+        assert!(matches!(slot, Some(Slot::Waiting(_))));
+        drop(first);
+        assert!(take_slot(&server, &config, &second).await.is_none());
+
+        // Slot freed once first stops waiting
+        drop(slot);
+        assert!(take_slot(&server, &config, &second).await.is_some());
+    }
+
+    #[tokio::test]
+    async fn should_queue_when_full() {
+        let mut config: Config = toml::from_str("[server]\ncommand = \"true\"").unwrap();
+        let server = Server::default();
+        server.update_status(&config, Some(online_status(20))).await;
+        assert!(!should_queue(&config, &server).await);
+
+        config.join.queue.enabled = true;
+        assert!(should_queue(&config, &server).await);
+
+        // Slot free, but others are queued already
+        server.update_status(&config, Some(online_status(19))).await;
+        assert!(!should_queue(&config, &server).await);
+        let ticket = server.queue.join();
+        assert!(should_queue(&config, &server).await);
+        drop(ticket);
+
+        // Released one at a time while online
+        let first = server.queue.join();
+        let second = server.queue.join();
+        assert!(matches!(
+            take_slot(&server, &config, &first).await,
+            Some(Slot::Online)
+        ));
+        drop(first);
+        assert!(take_slot(&server, &config, &second).await.is_none());
+    }
+
+    #[test]
+    fn message_position() {
+        let mut config: Config = toml::from_str("[server]\ncommand = \"true\"").unwrap();
+        config.join.queue.message = "Position {position}".into();
+        assert_eq!(message(&config, 3), "Position 3");
+    }
+}
@@ -17,6 +17,7 @@ use tokio::time;
 
 use crate::config::*;
 use crate::forge;
+use crate::join::queue::{self, Slot};
 use crate::mc::uuid;
 use crate::net;
 use crate::proto;
@@ -29,6 +30,9 @@ use crate::server::{Server, State};
 /// Interval to send keep-alive packets at.
 pub const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(10);
 
+/// Interval to check for a free slot and update the position of queued clients at.
+const QUEUE_INTERVAL: Duration = Duration::from_secs(1);
+
 /// Timeout for creating new server connection for lobby client.
 const SERVER_CONNECT_TIMEOUT: Duration = Duration::from_secs(2 * 60);
 
@@ -47,7 +51,8 @@ const SERVER_WARMUP: Duration = Duration::from_secs(1);
 
 /// Serve lobby service for given client connection.
 ///
-/// The client must be in the login state, or this will error.
+/// If `queued` is set, the client waits in the join queue in the lobby until a slot is free. The
+/// client must be in the login state, or this will error.
 // TODO: do not drop error here, return Box<dyn Error>
 // TODO: on error, nicely kick client with message
 pub async fn serve(
@@ -57,6 +62,7 @@ pub async fn serve(
     config: Arc<Config>,
     server: Arc<Server>,
     queue: BytesMut,
+    queued: bool,
 ) -> Result<(), ()> {
     let (mut reader, mut writer) = inbound.split();
 
@@ -105,29 +111,36 @@ pub async fn serve(
                 writer = returned_writer;
             }
 
-            // Respond with set compression if compression is enabled based on threshold
-            if proto::COMPRESSION_THRESHOLD >= 0 {
-                trace!(target: "lazymc::lobby", "Enabling compression for lobby client because server has it enabled (threshold: {})", proto::COMPRESSION_THRESHOLD);
-                respond_set_compression(client, &mut writer, proto::COMPRESSION_THRESHOLD).await?;
-                client.set_compression(proto::COMPRESSION_THRESHOLD);
+            // Complete login, get client into lobby world
+            login_lobby(client, &client_info, &mut writer, &server, &login_start).await?;
+
+            // Wait in join queue for a free slot
+            let _slot = if queued {
+                Some(
+                    stage_queue(
+                        client,
+                        &client_info,
+                        &server,
+                        &config,
+                        &mut reader,
+                        &mut writer,
+                        &mut inbound_buf,
+                    )
+                    .await?,
+                )
+            } else {
+                None
+            };
+
+            // Wait for server to come online, unless online already, such as when released from queue
+            if server.state() != State::Started {
+                stage_wait(client, &client_info, &server, &config, &mut writer).await?;
             }
 
-            // Respond with login success, switch to play state
-            respond_login_success(client, &mut writer, &login_start).await?;
-            client.set_state(ClientState::Play);
-
-            trace!(target: "lazymc::lobby", "Client login success, sending required play packets for lobby world");
-
-            // Send packets to client required to get into workable play state for lobby world
-            send_lobby_play_packets(client, &client_info, &mut writer, &server).await?;
-
-            // Wait for server to come online
-            stage_wait(client, &client_info, &server, &config, &mut writer).await?;
-
             // Start new connection to server
             let server_client_info = client_info.clone();
             let (server_client, mut outbound, mut server_buf) =
-                connect_to_server(&server_client_info, &inbound, &config).await?;
+                connect_to_server(&server_client_info, &inbound, &config, &server).await?;
             let (returned_reader, returned_writer) = inbound.split();
             reader = returned_reader;
             writer = returned_writer;
@@ -164,7 +177,8 @@ pub async fn serve(
 
             // Client and server connection ready now, move client to proxy
             debug!(target: "lazymc::lobby", "Server connection ready, relaying lobby client to proxy");
-            route_proxy(inbound, outbound, server_buf);
+            let options = proxy::ProxyOptions::from_config(&config, &server.proxy_activity);
+            route_proxy(inbound, outbound, server_buf, options);
 
             return Ok(());
         }
@@ -181,6 +195,93 @@ pub async fn serve(
     Ok(())
 }
 
+/// Serve honeypot lobby for given client connection.
+///
+/// Like the lobby, but the client is kept in it until it disconnects and the server is never
+/// woken. Client packets are logged at trace level. The client must be in the login state, or
+/// this will error.
+pub async fn serve_honeypot(
+    client: &Client,
+    client_info: ClientInfo,
+    mut inbound: TcpStream,
+    config: Arc<Config>,
+    server: Arc<Server>,
+    queue: BytesMut,
+) -> Result<(), ()> {
+    let (mut reader, mut writer) = inbound.split();
+
+    // Client must be in login state
+    if client.state() != ClientState::Login {
+        error!(target: "lazymc::honeypot", "Client reached honeypot with invalid state: {:?}", client.state());
+        return Err(());
+    }
+
+    // Incoming buffer
+    let mut inbound_buf = queue;
+
+    // Read login start packet
+    let login_start = match packet::read_packet(client, &mut inbound_buf, &mut reader).await {
+        Ok(Some((packet, _raw))) if packet.id == packets::login::SERVER_LOGIN_START => {
+            LoginStart::decode(&mut packet.data.as_slice()).map_err(|_| ())?
+        }
+        _ => return Err(()),
+    };
+
+    // Complete login, get client into lobby world
+    login_lobby(client, &client_info, &mut writer, &server, &login_start).await?;
+
+    let ip = server.display_ip(&config, client.peer.ip());
+    info!(target: "lazymc::honeypot", "Client {} joined honeypot lobby (user: {})", ip, login_start.name);
+
+    // Keep client in lobby, log all received packets until it disconnects
+    let log_packets = async {
+        loop {
+            match packet::read_packet(client, &mut inbound_buf, &mut reader).await {
+                Ok(Some((packet, _raw))) => {
+                    trace!(target: "lazymc::honeypot", "Client {} sent packet 0x{:02X} ({} bytes)", ip, packet.id, packet.data.len());
+                }
+                Ok(None) => break Ok(()),
+                Err(_) => break Err(()),
+            }
+        }
+    };
+    let result = select! {
+        a = keep_alive_loop(client, &client_info, &mut writer, &config.honeypot.message) => a,
+        b = log_packets => b,
+    };
+
+    info!(target: "lazymc::honeypot", "Client {} left honeypot lobby", ip);
+    result
+}
+
+/// Complete login for lobby client, and send play packets to get it into the lobby world.
+///
+/// Enables compression if configured, responds with login success and switches client to the
+/// play state.
+async fn login_lobby(
+    client: &Client,
+    client_info: &ClientInfo,
+    writer: &mut WriteHalf<'_>,
+    server: &Server,
+    login_start: &LoginStart,
+) -> Result<(), ()> {
+    // Respond with set compression if compression is enabled based on threshold
+    if proto::COMPRESSION_THRESHOLD >= 0 {
+        trace!(target: "lazymc::lobby", "Enabling compression for lobby client because server has it enabled (threshold: {})", proto::COMPRESSION_THRESHOLD);
+        respond_set_compression(client, writer, proto::COMPRESSION_THRESHOLD).await?;
+        client.set_compression(proto::COMPRESSION_THRESHOLD);
+    }
+
+    // Respond with login success, switch to play state
+    respond_login_success(client, writer, login_start).await?;
+    client.set_state(ClientState::Play);
+
+    trace!(target: "lazymc::lobby", "Client login success, sending required play packets for lobby world");
+
+    // Send packets to client required to get into workable play state for lobby world
+    send_lobby_play_packets(client, client_info, writer, server).await
+}
+
 /// Respond to client with a set compression packet.
 async fn respond_set_compression(
     client: &Client,
@@ -261,7 +362,7 @@ async fn keep_alive_loop(
     client: &Client,
     client_info: &ClientInfo,
     writer: &mut WriteHalf<'_>,
-    config: &Config,
+    message: &str,
 ) -> Result<(), ()> {
     let mut interval = time::interval(KEEP_ALIVE_INTERVAL);
 
@@ -272,12 +373,65 @@ async fn keep_alive_loop(
 
         // Send keep alive and title packets
         packets::play::keep_alive::send(client, client_info, writer).await?;
-        packets::play::title::send(client, client_info, writer, &config.join.lobby.message).await?;
+        packets::play::title::send(client, client_info, writer, message).await?;
 
         // TODO: verify we receive correct keep alive response
     }
 }
 
+/// Queue stage.
+///
+/// In this stage the client waits in the join queue until it is first and a slot is free.
+///
+/// During this stage we keep sending keep-alive and title packets with the queue position to the
+/// client. Errors if the client disconnects, which removes it from the queue.
+async fn stage_queue<'a>(
+    client: &Client,
+    client_info: &ClientInfo,
+    server: &'a Server,
+    config: &Config,
+    reader: &mut ReadHalf<'_>,
+    writer: &mut WriteHalf<'_>,
+    inbound_buf: &mut BytesMut,
+) -> Result<Slot<'a>, ()> {
+    let ticket = server.queue.join();
+    debug!(target: "lazymc::lobby", "Lobby client joined queue at position {}", ticket.position());
+
+    let mut check = time::interval(QUEUE_INTERVAL);
+    let mut keep_alive = time::interval(KEEP_ALIVE_INTERVAL);
+    let mut position = ticket.position();
+
+    loop {
+        select! {
+            _ = check.tick() => {
+                if let Some(slot) = queue::take_slot(server, config, &ticket).await {
+                    debug!(target: "lazymc::lobby", "Slot free for queued lobby client, releasing it");
+                    return Ok(slot);
+                }
+
+                // Update shown position when others leave the queue
+                if ticket.position() != position {
+                    position = ticket.position();
+                    let message = queue::message(config, position);
+                    packets::play::title::send(client, client_info, writer, &message).await?;
+                }
+            }
+            _ = keep_alive.tick() => {
+                trace!(target: "lazymc::lobby", "Sending keep-alive sequence to queued lobby client");
+                let message = queue::message(config, position);
+                packets::play::keep_alive::send(client, client_info, writer).await?;
+                packets::play::title::send(client, client_info, writer, &message).await?;
+            }
+            packet = packet::read_packet(client, inbound_buf, reader) => {
+                if !matches!(packet, Ok(Some(_))) {
+                    debug!(target: "lazymc::lobby", "Queued lobby client disconnected");
+                    return Err(());
+                }
+            }
+        }
+    }
+}
+
 /// Waiting stage.
 ///
 /// In this stage we wait for the server to come online.
@@ -291,7 +445,7 @@ async fn stage_wait(
     writer: &mut WriteHalf<'_>,
 ) -> Result<(), ()> {
     select! {
-        a = keep_alive_loop(client, client_info, writer, config) => a,
+        a = keep_alive_loop(client, client_info, writer, &config.join.lobby.message) => a,
         b = wait_for_server(server, config) => b,
     }
 }
@@ -358,10 +512,11 @@ async fn connect_to_server(
     client_info: &ClientInfo,
     inbound: &TcpStream,
     config: &Config,
+    server: &Server,
 ) -> Result<(Client, TcpStream, BytesMut), ()> {
     time::timeout(
         SERVER_CONNECT_TIMEOUT,
-        connect_to_server_no_timeout(client_info, inbound, config),
+        connect_to_server_no_timeout(client_info, inbound, config, server),
     )
     .await
     .map_err(|_| {
@@ -377,12 +532,16 @@ async fn connect_to_server_no_timeout(
     client_info: &ClientInfo,
     inbound: &TcpStream,
     config: &Config,
+    server: &Server,
 ) -> Result<(Client, TcpStream, BytesMut), ()> {
     // Open connection
     // TODO: on connect fail, ping server and redirect to serve_status if offline
-    let mut outbound = TcpStream::connect(config.server.address)
-        .await
-        .map_err(|_| ())?;
+    let mut outbound = net::connect(
+        &server.proxy_addresses(config),
+        config.server.connect_timeout(),
+    )
+    .await
+    .map_err(|_| ())?;
 
     // Add proxy header
     if config.server.send_proxy_v2 {
@@ -632,10 +791,15 @@ async fn wait_for_server_join_game_no_timeout(
 /// `inbound_queue` is used for data already received from the server, that needs to be pushed to
 /// the client.
 #[inline]
-pub fn route_proxy(inbound: TcpStream, outbound: TcpStream, inbound_queue: BytesMut) {
+pub fn route_proxy(
+    inbound: TcpStream,
+    outbound: TcpStream,
+    inbound_queue: BytesMut,
+    options: proxy::ProxyOptions,
+) {
     // When server is online, proxy all
     let service = async move {
-        proxy::proxy_inbound_outbound_with_queue(inbound, outbound, &inbound_queue, &[])
+        proxy::proxy_inbound_outbound_with_queue(inbound, outbound, &inbound_queue, &[], options)
             .map(|r| {
                 if let Err(err) = r {
                     warn!(target: "lazymc", "Failed to proxy: {}", err);
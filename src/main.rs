@@ -15,6 +15,7 @@ pub(crate) mod join;
 #[cfg(feature = "lobby")]
 pub(crate) mod lobby;
 pub(crate) mod mc;
+pub(crate) mod metrics;
 pub(crate) mod monitor;
 pub(crate) mod net;
 pub(crate) mod os;
@@ -10,6 +10,9 @@ use serde::Deserialize;
 /// File name.
 pub const FILE: &str = "banned-ips.json";
 
+/// Banned players file name.
+pub const PLAYERS_FILE: &str = "banned-players.json";
+
 /// The forever expiry literal.
 const EXPIRY_FOREVER: &str = "forever";
 
@@ -60,28 +63,92 @@ pub struct BannedIp {
 impl BannedIp {
     /// Check if this entry is currently banned.
     pub fn is_banned(&self) -> bool {
-        // Get expiry time
-        let expires = match &self.expires {
-            Some(expires) => expires,
-            None => return true,
-        };
+        expiry_is_banned(self.expires.as_deref())
+    }
+}
+
+/// List of banned players.
+#[derive(Debug, Default)]
+pub struct BannedPlayers {
+    /// Banned players by UUID.
+    uuids: HashMap<u128, BannedPlayer>,
+
+    /// Banned players by lowercase username.
+    names: HashMap<String, BannedPlayer>,
+}
+
+impl BannedPlayers {
+    /// Get ban entry for player if it exists, matching UUID if known or username.
+    ///
+    /// This uses the latest known `banned-players.json` contents if known.
+    pub fn get(&self, uuid: Option<u128>, username: &str) -> Option<BannedPlayer> {
+        uuid.and_then(|uuid| self.uuids.get(&uuid))
+            .or_else(|| self.names.get(&username.to_lowercase()))
+            .cloned()
+    }
+}
+
+/// A banned player entry.
+#[derive(Debug, Deserialize, Clone)]
+pub struct BannedPlayer {
+    /// Banned player UUID.
+    pub uuid: Option<String>,
+
+    /// Banned player username.
+    pub name: Option<String>,
+
+    /// Ban creation time.
+    pub created: Option<String>,
+
+    /// Ban source.
+    pub source: Option<String>,
+
+    /// Ban expiry time.
+    pub expires: Option<String>,
+
+    /// Ban reason.
+    pub reason: Option<String>,
+}
+
+impl BannedPlayer {
+    /// Check if this entry is currently banned.
+    pub fn is_banned(&self) -> bool {
+        expiry_is_banned(self.expires.as_deref())
+    }
+}
+
+/// Check if a ban with the given expiry time is currently active.
+fn expiry_is_banned(expires: Option<&str>) -> bool {
+    // Get expiry time
+    let expires = match expires {
+        Some(expires) => expires,
+        None => return true,
+    };
+
+    // If expiry is forever, the user is banned
+    if expires.trim().to_lowercase() == EXPIRY_FOREVER {
+        return true;
+    }
 
-        // If expiry is forever, the user is banned
-        if expires.trim().to_lowercase() == EXPIRY_FOREVER {
+    // Parse expiry time, check if it has passed
+    let expiry = match DateTime::parse_from_str(expires, "%Y-%m-%d %H:%M:%S %z") {
+        Ok(expiry) => expiry,
+        Err(err) => {
+            error!(target: "lazymc", "Failed to parse ban expiry '{}', assuming still banned: {}", expires, err);
             return true;
         }
+    };
 
-        // Parse expiry time, check if it has passed
-        let expiry = match DateTime::parse_from_str(expires, "%Y-%m-%d %H:%M:%S %z") {
-            Ok(expiry) => expiry,
-            Err(err) => {
-                error!(target: "lazymc", "Failed to parse ban expiry '{}', assuming still banned: {}", expires, err);
-                return true;
-            }
-        };
+    expiry > Utc::now()
+}
 
-        expiry > Utc::now()
+/// Parse hyphenated or simple UUID string.
+fn parse_uuid(uuid: &str) -> Option<u128> {
+    let hex = uuid.replace('-', "");
+    if hex.len() != 32 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
     }
+    u128::from_str_radix(&hex, 16).ok()
 }
 
 /// Load banned IPs from file.
@@ -97,3 +164,70 @@ pub fn load(path: &Path) -> Result<BannedIps, Box<dyn Error>> {
     let ips = ips.into_iter().map(|ip| (ip.ip, ip)).collect();
     Ok(BannedIps { ips })
 }
+
+/// Load banned players from file.
+pub fn load_players(path: &Path) -> Result<BannedPlayers, Box<dyn Error>> {
+    // Load file contents
+    let contents = fs::read_to_string(path)?;
+
+    // Parse contents
+    let players: Vec<BannedPlayer> = serde_json::from_str(&contents)?;
+    debug!(target: "lazymc", "Loaded {} banned players", players.len());
+
+    // Transform into maps by UUID and username
+    let mut banned = BannedPlayers::default();
+    for player in players {
+        if let Some(uuid) = player.uuid.as_deref().and_then(parse_uuid) {
+            banned.uuids.insert(uuid, player.clone());
+        }
+        if let Some(name) = &player.name {
+            banned.names.insert(name.to_lowercase(), player);
+        }
+    }
+    Ok(banned)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_uuids() {
+        let uuid = 0x069a79f444e94726a5befca90e38aaf5;
+        assert_eq!(
+            parse_uuid("069a79f4-44e9-4726-a5be-fca90e38aaf5"),
+            Some(uuid)
+        );
+        assert_eq!(parse_uuid("069a79f444e94726a5befca90e38aaf5"), Some(uuid));
+        assert_eq!(
+            parse_uuid("069A79F4-44E9-4726-A5BE-FCA90E38AAF5"),
+            Some(uuid)
+        );
+        assert_eq!(parse_uuid("069a79f4-44e9-4726-a5be"), None);
+        assert_eq!(parse_uuid("+69a79f444e94726a5befca90e38aaf5"), None);
+        assert_eq!(parse_uuid(""), None);
+    }
+
+    #[test]
+    fn get_player_ban() {
+        let player = BannedPlayer {
+            uuid: Some("069a79f4-44e9-4726-a5be-fca90e38aaf5".into()),
+            name: Some("Notch".into()),
+            created: None,
+            source: None,
+            expires: None,
+            reason: None,
+        };
+        let banned = BannedPlayers {
+            uuids: HashMap::from([(0x069a79f444e94726a5befca90e38aaf5, player.clone())]),
+            names: HashMap::from([("notch".into(), player)]),
+        };
+
+        assert!(banned
+            .get(Some(0x069a79f444e94726a5befca90e38aaf5), "Renamed")
+            .is_some());
+        assert!(banned.get(None, "NOTCH").is_some());
+        assert!(banned.get(Some(1), "Other").is_none());
+        assert!(banned.get(None, "Other").is_none());
+    }
+}
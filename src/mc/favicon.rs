@@ -5,6 +5,15 @@ use crate::proto::client::ClientInfo;
 /// Protocol version since when favicons are supported.
 const FAVICON_PROTOCOL_VERSION: u32 = 4;
 
+/// Favicon data URI prefix.
+const FAVICON_PREFIX: &str = "data:image/png;base64,";
+
+/// Favicon image size in pixels.
+const FAVICON_SIZE: u32 = 64;
+
+/// PNG file signature.
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
 /// Get default server status favicon.
 pub fn default_favicon() -> String {
     encode_favicon(include_bytes!("../../res/unknown_server_optimized.png"))
@@ -16,7 +25,7 @@ pub fn default_favicon() -> String {
 pub fn encode_favicon(data: &[u8]) -> String {
     format!(
         "{}{}",
-        "data:image/png;base64,",
+        FAVICON_PREFIX,
         base64::engine::general_purpose::STANDARD.encode(data)
     )
 }
@@ -30,3 +39,88 @@ pub fn supports_favicon(client_info: &ClientInfo) -> bool {
         .map(|p| p >= FAVICON_PROTOCOL_VERSION)
         .unwrap_or(true)
 }
+
+/// Check whether the given encoded favicon is a valid 64x64 PNG image.
+///
+/// Used to validate favicons sent by the server, some modded servers send odd data.
+pub fn is_valid_favicon(favicon: &str) -> bool {
+    let data = match favicon
+        .strip_prefix(FAVICON_PREFIX)
+        .map(|data| base64::engine::general_purpose::STANDARD.decode(data.replace('\n', "")))
+    {
+        Some(Ok(data)) => data,
+        _ => return false,
+    };
+
+    // Must have PNG signature, followed by IHDR chunk with image size
+    if data.len() < 24 || !data.starts_with(PNG_SIGNATURE) || &data[12..16] != b"IHDR" {
+        return false;
+    }
+    let width = u32::from_be_bytes([data[16], data[17], data[18], data[19]]);
+    let height = u32::from_be_bytes([data[20], data[21], data[22], data[23]]);
+    width == FAVICON_SIZE && height == FAVICON_SIZE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build PNG image data with just a signature and IHDR chunk of the given size.
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut data = PNG_SIGNATURE.to_vec();
+        data.extend(13u32.to_be_bytes());
+        data.extend(b"IHDR");
+        data.extend(width.to_be_bytes());
+        data.extend(height.to_be_bytes());
+        data.extend([8, 6, 0, 0, 0]);
+        data
+    }
+
+    #[test]
+    fn valid_favicon() {
+        assert!(is_valid_favicon(&encode_favicon(&png(64, 64))));
+
+        // Servers may wrap base64 data over multiple lines
+        let mut favicon = encode_favicon(&png(64, 64));
+        favicon.insert(FAVICON_PREFIX.len() + 8, '\n');
+        assert!(is_valid_favicon(&favicon));
+    }
+
+    #[test]
+    fn bad_prefix_favicon() {
+        let favicon = encode_favicon(&png(64, 64));
+        let data = favicon.strip_prefix(FAVICON_PREFIX).unwrap();
+        assert!(!is_valid_favicon(data));
+        assert!(!is_valid_favicon(&format!("data:image/jpeg;base64,{data}")));
+        assert!(!is_valid_favicon(&format!("{FAVICON_PREFIX}not base64!")));
+        assert!(!is_valid_favicon(""));
+    }
+
+    #[test]
+    fn oversized_favicon() {
+        assert!(!is_valid_favicon(&encode_favicon(&png(128, 128))));
+        assert!(!is_valid_favicon(&encode_favicon(&png(u32::MAX, u32::MAX))));
+    }
+
+    #[test]
+    fn non_square_favicon() {
+        assert!(!is_valid_favicon(&encode_favicon(&png(64, 32))));
+        assert!(!is_valid_favicon(&encode_favicon(&png(32, 64))));
+        assert!(!is_valid_favicon(&encode_favicon(&png(0, 0))));
+    }
+
+    #[test]
+    fn malformed_favicon() {
+        // Truncated IHDR chunk
+        let data = png(64, 64);
+        assert!(!is_valid_favicon(&encode_favicon(&data[..20])));
+
+        // Missing PNG signature
+        assert!(!is_valid_favicon(&encode_favicon(&data[8..])));
+
+        // Other chunk than IHDR first
+        let mut data = png(64, 64);
+        data[12..16].copy_from_slice(b"IDAT");
+        assert!(!is_valid_favicon(&encode_favicon(&data)));
+    }
+}
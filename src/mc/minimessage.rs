@@ -0,0 +1,554 @@
+use minecraft_protocol::data::chat::Message;
+use serde_json::{json, Map, Value};
+
+use crate::proto::client::ClientInfo;
+
+/// Named chat colors, with their RGB value.
+const COLORS: [(&str, u32); 16] = [
+    ("black", 0x000000),
+    ("dark_blue", 0x0000AA),
+    ("dark_green", 0x00AA00),
+    ("dark_aqua", 0x00AAAA),
+    ("dark_red", 0xAA0000),
+    ("dark_purple", 0xAA00AA),
+    ("gold", 0xFFAA00),
+    ("gray", 0xAAAAAA),
+    ("dark_gray", 0x555555),
+    ("blue", 0x5555FF),
+    ("green", 0x55FF55),
+    ("aqua", 0x55FFFF),
+    ("red", 0xFF5555),
+    ("light_purple", 0xFF55FF),
+    ("yellow", 0xFFFF55),
+    ("white", 0xFFFFFF),
+];
+
+/// Text decorations, with their tag name aliases.
+const DECORATIONS: [(&str, &[&str]); 5] = [
+    ("bold", &["bold", "b"]),
+    ("italic", &["italic", "i", "em"]),
+    ("underlined", &["underlined", "u"]),
+    ("strikethrough", &["strikethrough", "st"]),
+    ("obfuscated", &["obfuscated", "obf"]),
+];
+
+/// Protocol version since when hex colors are supported (1.16).
+const HEX_COLOR_PROTOCOL_VERSION: u32 = 735;
+
+/// Parse MiniMessage formatted text into a chat message.
+///
+/// Supports colors, decorations, gradients, `<reset>` and `<newline>`. Unknown and invalid tags
+/// are kept as literal text. Hex colors are replaced with the nearest named color if `hex` is
+/// false. Returns `None` if building the message fails.
+pub fn parse(text: &str, hex: bool) -> Option<Message> {
+    let tokens = tokenize(text);
+    let components = render(&tokens);
+
+    // Hex colors may not be supported, fall back to nearest named colors
+    hex.then(|| to_message(&components, true))
+        .flatten()
+        .or_else(|| to_message(&components, false))
+}
+
+/// Check whether hex colors are supported based on the given client info.
+///
+/// Defaults to `true` if unsure.
+pub fn supports_hex_colors(client_info: &ClientInfo) -> bool {
+    client_info
+        .protocol
+        .map(|p| p >= HEX_COLOR_PROTOCOL_VERSION)
+        .unwrap_or(true)
+}
+
+/// A chat color.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Color {
+    /// Named color, index in `COLORS`.
+    Named(usize),
+
+    /// RGB color.
+    Rgb(u32),
+}
+
+impl Color {
+    /// Parse color from name or `#rrggbb` hex string.
+    fn parse(color: &str) -> Option<Self> {
+        if let Some(hex) = color.strip_prefix('#') {
+            if hex.len() != 6 {
+                return None;
+            }
+            return u32::from_str_radix(hex, 16).ok().map(Self::Rgb);
+        }
+
+        COLORS
+            .iter()
+            .position(|(name, _)| name.eq_ignore_ascii_case(color))
+            .map(Self::Named)
+    }
+
+    /// Get RGB value.
+    fn rgb(self) -> u32 {
+        match self {
+            Self::Named(index) => COLORS[index].1,
+            Self::Rgb(rgb) => rgb,
+        }
+    }
+
+    /// Get JSON chat color value, as hex string if allowed or nearest named color otherwise.
+    fn to_json(self, hex: bool) -> Value {
+        match self {
+            Self::Named(index) => COLORS[index].0.into(),
+            Self::Rgb(rgb) if hex => format!("#{rgb:06x}").into(),
+            Self::Rgb(rgb) => COLORS[nearest_named(rgb)].0.into(),
+        }
+    }
+}
+
+/// A formatting tag.
+#[derive(Debug, Clone)]
+enum Tag {
+    /// Color tag.
+    Color(Color),
+
+    /// Decoration tag, index in `DECORATIONS` and whether it is enabled.
+    Decoration(usize, bool),
+
+    /// Gradient tag, with colors and number of characters it spans.
+    Gradient(Vec<Color>, usize),
+}
+
+impl Tag {
+    /// Name used to match closing tags.
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Color(_) => "color",
+            Self::Decoration(index, _) => DECORATIONS[*index].0,
+            Self::Gradient(..) => "gradient",
+        }
+    }
+}
+
+/// A parsed token.
+#[derive(Debug)]
+enum Token {
+    /// Plain text.
+    Text(String),
+
+    /// Opening tag.
+    Open(Tag),
+
+    /// Closing tag, with tag name.
+    Close(&'static str),
+
+    /// Reset all formatting.
+    Reset,
+}
+
+/// Style of a text component.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+struct Style {
+    color: Option<Color>,
+    decorations: [Option<bool>; 5],
+}
+
+/// Split text into tokens.
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = vec![];
+    let mut literal = String::new();
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            // Escaped character
+            '\\' if matches!(chars.peek(), Some((_, '<' | '\\'))) => {
+                literal.push(chars.next().unwrap().1);
+            }
+
+            // Tag, keep as literal if not closed, unknown or invalid
+            '<' => {
+                let end = match text[i..].find('>') {
+                    Some(end) => i + end,
+                    None => {
+                        literal.push(c);
+                        continue;
+                    }
+                };
+
+                match parse_tag(&text[i + 1..end]) {
+                    Some(token) => {
+                        if !literal.is_empty() {
+                            tokens.push(Token::Text(std::mem::take(&mut literal)));
+                        }
+                        tokens.push(token);
+                    }
+                    None => literal.push_str(&text[i..=end]),
+                }
+
+                // Skip tag contents
+                while chars.peek().map(|(j, _)| *j <= end).unwrap_or(false) {
+                    chars.next();
+                }
+            }
+
+            c => literal.push(c),
+        }
+    }
+
+    if !literal.is_empty() {
+        tokens.push(Token::Text(literal));
+    }
+
+    measure_gradients(&mut tokens);
+    tokens
+}
+
+/// Parse tag contents, between `<` and `>`.
+///
+/// Returns `None` for unknown or invalid tags, such as a color tag without a valid color.
+fn parse_tag(tag: &str) -> Option<Token> {
+    let tag = tag.trim();
+
+    // Closing tag
+    if let Some(name) = tag.strip_prefix('/') {
+        let name = name.split(':').next().unwrap_or_default();
+        return close_name(name).map(Token::Close);
+    }
+
+    let mut args = tag.split(':');
+    let name = args.next().unwrap_or_default().to_ascii_lowercase();
+    let token = match name.as_str() {
+        "reset" => Token::Reset,
+        "newline" | "br" => Token::Text("\n".into()),
+        "color" | "colour" | "c" => Token::Open(Tag::Color(Color::parse(args.next()?)?)),
+        "gradient" => {
+            let colors = args.map(Color::parse).collect::<Option<Vec<_>>>()?;
+            if colors.len() < 2 {
+                return None;
+            }
+            Token::Open(Tag::Gradient(colors, 0))
+        }
+        name => {
+            let (name, enabled) = match name.strip_prefix('!') {
+                Some(name) => (name, false),
+                None => (name, true),
+            };
+
+            if let Some(index) = decoration_index(name) {
+                Token::Open(Tag::Decoration(index, enabled))
+            } else if let (true, Some(color)) = (enabled, Color::parse(name)) {
+                Token::Open(Tag::Color(color))
+            } else {
+                return None;
+            }
+        }
+    };
+
+    Some(token)
+}
+
+/// Get tag name to match for closing tag, `None` if unknown.
+fn close_name(name: &str) -> Option<&'static str> {
+    let name = name.to_ascii_lowercase();
+    match name.as_str() {
+        "color" | "colour" | "c" => Some("color"),
+        "gradient" => Some("gradient"),
+        name => match decoration_index(name) {
+            Some(index) => Some(DECORATIONS[index].0),
+            None => Color::parse(name).map(|_| "color"),
+        },
+    }
+}
+
+/// Find decoration index by tag name.
+fn decoration_index(name: &str) -> Option<usize> {
+    DECORATIONS
+        .iter()
+        .position(|(_, aliases)| aliases.contains(&name))
+}
+
+/// Count number of characters each gradient spans, so colors can be interpolated.
+fn measure_gradients(tokens: &mut [Token]) {
+    for i in 0..tokens.len() {
+        if !matches!(tokens[i], Token::Open(Tag::Gradient(..))) {
+            continue;
+        }
+
+        let mut depth = 0;
+        let mut len = 0;
+        for token in &tokens[i + 1..] {
+            match token {
+                Token::Text(text) => len += text.chars().count(),
+                Token::Open(Tag::Gradient(..)) => depth += 1,
+                Token::Close("gradient") if depth == 0 => break,
+                Token::Close("gradient") => depth -= 1,
+                Token::Reset => break,
+                _ => {}
+            }
+        }
+
+        if let Token::Open(Tag::Gradient(_, span)) = &mut tokens[i] {
+            *span = len;
+        }
+    }
+}
+
+/// Render tokens into text components with style.
+fn render(tokens: &[Token]) -> Vec<(String, Style)> {
+    // Stack of open tags, with number of characters rendered in it
+    let mut stack: Vec<(&Tag, usize)> = vec![];
+    let mut components: Vec<(String, Style)> = vec![];
+
+    for token in tokens {
+        match token {
+            Token::Open(tag) => stack.push((tag, 0)),
+            Token::Close(name) => {
+                if let Some(index) = stack.iter().rposition(|(tag, _)| tag.name() == *name) {
+                    stack.remove(index);
+                }
+            }
+            Token::Reset => stack.clear(),
+            Token::Text(text) => {
+                for c in text.chars() {
+                    let style = current_style(&mut stack);
+                    match components.last_mut() {
+                        Some((text, last)) if *last == style => text.push(c),
+                        _ => components.push((c.to_string(), style)),
+                    }
+                }
+            }
+        }
+    }
+
+    components
+}
+
+/// Get style for the next character, advances the active gradient.
+fn current_style(stack: &mut [(&Tag, usize)]) -> Style {
+    let mut style = Style::default();
+
+    for (tag, _) in stack.iter() {
+        if let Tag::Decoration(index, enabled) = tag {
+            style.decorations[*index] = Some(*enabled);
+        }
+    }
+
+    let active = stack
+        .iter_mut()
+        .rev()
+        .find(|(tag, _)| matches!(tag, Tag::Color(_) | Tag::Gradient(..)));
+    style.color = match active {
+        Some((Tag::Color(color), _)) => Some(*color),
+        Some((Tag::Gradient(colors, span), pos)) => {
+            *pos += 1;
+            Some(Color::Rgb(gradient(colors, *span, *pos - 1)))
+        }
+        _ => None,
+    };
+
+    style
+}
+
+/// Interpolate gradient color at given position.
+fn gradient(colors: &[Color], span: usize, pos: usize) -> u32 {
+    let t = if span > 1 {
+        pos.min(span - 1) as f32 / (span - 1) as f32
+    } else {
+        0.0
+    };
+
+    let segment = t * (colors.len() - 1) as f32;
+    let index = (segment as usize).min(colors.len() - 2);
+    let local = segment - index as f32;
+    let (from, to) = (colors[index].rgb(), colors[index + 1].rgb());
+
+    [16, 8, 0].iter().fold(0, |rgb, shift| {
+        let (a, b) = (
+            ((from >> shift) & 0xFF) as f32,
+            ((to >> shift) & 0xFF) as f32,
+        );
+        rgb | (((a + (b - a) * local).round() as u32) << shift)
+    })
+}
+
+/// Find index of nearest named color for RGB value.
+fn nearest_named(rgb: u32) -> usize {
+    let distance = |other: u32| {
+        [16, 8, 0]
+            .iter()
+            .map(|shift| {
+                let diff = ((rgb >> shift) & 0xFF) as i32 - ((other >> shift) & 0xFF) as i32;
+                diff * diff
+            })
+            .sum::<i32>()
+    };
+
+    (0..COLORS.len())
+        .min_by_key(|index| distance(COLORS[*index].1))
+        .unwrap()
+}
+
+/// Build chat message from components.
+fn to_message(components: &[(String, Style)], hex: bool) -> Option<Message> {
+    let extra = components
+        .iter()
+        .map(|(text, style)| {
+            let mut component = Map::new();
+            component.insert("text".into(), text.clone().into());
+            if let Some(color) = style.color {
+                component.insert("color".into(), color.to_json(hex));
+            }
+            for (index, enabled) in style.decorations.iter().enumerate() {
+                if let Some(enabled) = enabled {
+                    component.insert(DECORATIONS[index].0.into(), (*enabled).into());
+                }
+            }
+            Value::Object(component)
+        })
+        .collect::<Vec<_>>();
+
+    serde_json::from_value(json!({ "text": "", "extra": extra })).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Index of red in `COLORS`.
+    const RED: usize = 12;
+
+    /// Parse text into styled components.
+    fn components(text: &str) -> Vec<(String, Style)> {
+        render(&tokenize(text))
+    }
+
+    /// Build style with the given color and decorations.
+    fn style(color: Option<Color>, decorations: [Option<bool>; 5]) -> Style {
+        Style { color, decorations }
+    }
+
+    #[test]
+    fn colors() {
+        let red = style(Some(Color::Named(RED)), Default::default());
+        let expected = vec![
+            ("a".to_string(), red.clone()),
+            ("b".into(), Style::default()),
+        ];
+        assert_eq!(components("<red>a</red>b"), expected);
+        assert_eq!(components("<color:red>a</color>b"), expected);
+        assert_eq!(components("<c:RED>a</c>b"), expected);
+        assert_eq!(
+            components("<#123456>a"),
+            vec![(
+                "a".into(),
+                style(Some(Color::Rgb(0x123456)), Default::default())
+            )]
+        );
+    }
+
+    #[test]
+    fn decorations() {
+        assert_eq!(
+            components("<b>a<!b>b</b></b>c"),
+            vec![
+                (
+                    "a".into(),
+                    style(None, [Some(true), None, None, None, None])
+                ),
+                (
+                    "b".into(),
+                    style(None, [Some(false), None, None, None, None])
+                ),
+                ("c".into(), Style::default()),
+            ]
+        );
+        assert_eq!(
+            components("<em><st>a"),
+            vec![(
+                "a".into(),
+                style(None, [None, Some(true), None, Some(true), None])
+            )]
+        );
+    }
+
+    #[test]
+    fn gradients() {
+        let colors = components("<gradient:#000000:#ffffff>abc</gradient>d")
+            .into_iter()
+            .map(|(text, style)| (text, style.color))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            colors,
+            vec![
+                ("a".into(), Some(Color::Rgb(0x000000))),
+                ("b".into(), Some(Color::Rgb(0x808080))),
+                ("c".into(), Some(Color::Rgb(0xffffff))),
+                ("d".into(), None),
+            ]
+        );
+    }
+
+    #[test]
+    fn reset() {
+        assert_eq!(
+            components("<red><b>a<reset>b"),
+            vec![
+                (
+                    "a".into(),
+                    style(
+                        Some(Color::Named(RED)),
+                        [Some(true), None, None, None, None]
+                    )
+                ),
+                ("b".into(), Style::default()),
+            ]
+        );
+    }
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            components("\\<red>a\\\\b"),
+            vec![("<red>a\\b".into(), Style::default())]
+        );
+    }
+
+    #[test]
+    fn invalid_tags_are_literal() {
+        for text in [
+            "<color>a",
+            "<color:bogus>a",
+            "<#12345>a",
+            "<gradient:red>a",
+            "<gradient:red:bogus>a",
+            "<unknown>a",
+            "</unknown>a",
+            "<!red>a",
+            "<red a",
+        ] {
+            assert_eq!(
+                components(text),
+                vec![(text.to_string(), Style::default())],
+                "{text}"
+            );
+        }
+
+        // Valid tags around invalid ones still apply
+        assert_eq!(
+            components("<red><color>a"),
+            vec![(
+                "<color>a".into(),
+                style(Some(Color::Named(RED)), Default::default())
+            )]
+        );
+        assert!(parse("<color:bogus>Sleeping", true).is_some());
+    }
+
+    #[test]
+    fn hex_fallback_to_nearest_named() {
+        assert_eq!(Color::Rgb(0xff0000).to_json(true), "#ff0000");
+        assert_eq!(Color::Rgb(0xff0000).to_json(false), "dark_red");
+        assert_eq!(Color::Rgb(0xfe5656).to_json(false), "red");
+        assert_eq!(Color::Named(RED).to_json(false), "red");
+        assert!(parse("<#ff0000>Sleeping", false).is_some());
+    }
+}
@@ -2,6 +2,7 @@ pub mod ban;
 #[cfg(feature = "lobby")]
 pub mod dimension;
 pub mod favicon;
+pub mod minimessage;
 #[cfg(feature = "rcon")]
 pub mod rcon;
 pub mod server_properties;
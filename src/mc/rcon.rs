@@ -48,7 +48,7 @@ impl Rcon {
     /// Connect to a host from the given configuration.
     pub async fn connect_config(config: &Config) -> Result<Self, Box<dyn std::error::Error>> {
         // RCON address
-        let mut addr = config.server.address;
+        let mut addr = config.server.address();
         addr.set_port(config.rcon.port);
         let addr = addr.to_string();
 
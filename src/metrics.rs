@@ -0,0 +1,358 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Maximum number of protocol versions to count separately in a counter.
+///
+/// Clients choose the protocol version they send, others are counted as unknown to bound memory.
+/// Includes the tag for unknown protocol versions.
+const MAX_PROTOCOL_TAGS: usize = 32;
+
+/// Protocol health and handoff metrics.
+///
+/// Counts protocol errors from clients, useful to detect misbehaving clients or protocol drift.
+/// Times handoffs of joining clients to the server, useful to diagnose slow joins.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Handshake packets that failed to decode.
+    pub handshake_errors: Counter,
+
+    /// Login start packets that failed to decode.
+    pub login_start_errors: Counter,
+
+    /// Packets received from clients that were not handled.
+    pub unhandled_packets: Counter,
+
+    /// Handoffs of clients joining an already started server.
+    pub handoff_started: Timing,
+
+    /// Handoffs of clients that waited for the server to start.
+    pub handoff_cold_started: Timing,
+}
+
+impl Metrics {
+    /// Take a snapshot of all counters.
+    ///
+    /// Counters are cumulative since lazymc started, use `MetricsSnapshot::since` to report
+    /// metrics over an interval.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            handshake_errors: self.handshake_errors.snapshot(),
+            login_start_errors: self.login_start_errors.snapshot(),
+            unhandled_packets: self.unhandled_packets.snapshot(),
+            handoff_started: self.handoff_started.snapshot(),
+            handoff_cold_started: self.handoff_cold_started.snapshot(),
+        }
+    }
+}
+
+/// A counter, tagged by client protocol version.
+#[derive(Debug, Default)]
+pub struct Counter {
+    /// Total count.
+    total: AtomicU64,
+
+    /// Count for each client protocol version, `None` if unknown.
+    by_protocol: Mutex<BTreeMap<Option<u32>, u64>>,
+}
+
+impl Counter {
+    /// Increase counter for the given client protocol version.
+    ///
+    /// Counted as unknown if too many protocol versions are tracked already.
+    pub fn incr(&self, protocol: Option<u32>) {
+        self.total.fetch_add(1, Ordering::Relaxed);
+
+        let mut by_protocol = self.by_protocol.lock().unwrap();
+        let protocol = protocol.filter(|p| {
+            by_protocol.contains_key(&Some(*p)) || by_protocol.len() < MAX_PROTOCOL_TAGS - 1
+        });
+        *by_protocol.entry(protocol).or_default() += 1;
+    }
+
+    /// Get current counts.
+    pub fn snapshot(&self) -> CounterSnapshot {
+        let by_protocol = self.by_protocol.lock().unwrap();
+        CounterSnapshot {
+            total: self.total.load(Ordering::Relaxed),
+            by_protocol: by_protocol.clone(),
+        }
+    }
+}
+
+/// A timing, recording number of events and their duration.
+#[derive(Debug, Default)]
+pub struct Timing(Mutex<TimingSnapshot>);
+
+impl Timing {
+    /// Record an event that took the given duration.
+    pub fn record(&self, duration: Duration) {
+        let mut timing = self.0.lock().unwrap();
+        timing.count += 1;
+        timing.total += duration;
+        timing.max = timing.max.max(duration);
+    }
+
+    /// Get current timing.
+    pub fn snapshot(&self) -> TimingSnapshot {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// Snapshot of all metrics.
+#[derive(Debug, Clone, Default)]
+pub struct MetricsSnapshot {
+    pub handshake_errors: CounterSnapshot,
+    pub login_start_errors: CounterSnapshot,
+    pub unhandled_packets: CounterSnapshot,
+    pub handoff_started: TimingSnapshot,
+    pub handoff_cold_started: TimingSnapshot,
+}
+
+impl MetricsSnapshot {
+    /// Get metrics since the given earlier snapshot.
+    ///
+    /// The maximum handoff time can't be derived, it is the maximum since lazymc started.
+    pub fn since(&self, earlier: &Self) -> Self {
+        Self {
+            handshake_errors: self.handshake_errors.since(&earlier.handshake_errors),
+            login_start_errors: self.login_start_errors.since(&earlier.login_start_errors),
+            unhandled_packets: self.unhandled_packets.since(&earlier.unhandled_packets),
+            handoff_started: self.handoff_started.since(&earlier.handoff_started),
+            handoff_cold_started: self
+                .handoff_cold_started
+                .since(&earlier.handoff_cold_started),
+        }
+    }
+
+    /// Whether all protocol error counters are zero.
+    pub fn has_protocol_errors(&self) -> bool {
+        self.handshake_errors.total > 0
+            || self.login_start_errors.total > 0
+            || self.unhandled_packets.total > 0
+    }
+
+    /// Whether any handoff was timed.
+    pub fn has_handoffs(&self) -> bool {
+        self.handoff_started.count > 0 || self.handoff_cold_started.count > 0
+    }
+
+    /// Render metrics in Prometheus text exposition format.
+    pub fn prometheus(&self) -> String {
+        let mut out = String::new();
+        for (name, help, counter) in [
+            (
+                "lazymc_handshake_errors_total",
+                "Handshake packets that failed to decode.",
+                &self.handshake_errors,
+            ),
+            (
+                "lazymc_login_start_errors_total",
+                "Login start packets that failed to decode.",
+                &self.login_start_errors,
+            ),
+            (
+                "lazymc_unhandled_packets_total",
+                "Packets received from clients that were not handled.",
+                &self.unhandled_packets,
+            ),
+        ] {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} counter");
+            if counter.by_protocol.is_empty() {
+                let _ = writeln!(out, "{name} 0");
+            }
+            for (protocol, count) in &counter.by_protocol {
+                let protocol = protocol.map(|p| p.to_string());
+                let protocol = protocol.as_deref().unwrap_or("unknown");
+                let _ = writeln!(out, "{name}{{protocol=\"{protocol}\"}} {count}");
+            }
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP lazymc_handoff_seconds Time to hand off joining clients to the server."
+        );
+        let _ = writeln!(out, "# TYPE lazymc_handoff_seconds summary");
+        for (kind, timing) in [
+            ("started", &self.handoff_started),
+            ("cold_started", &self.handoff_cold_started),
+        ] {
+            let _ = writeln!(
+                out,
+                "lazymc_handoff_seconds_sum{{kind=\"{kind}\"}} {}",
+                timing.total.as_secs_f64()
+            );
+            let _ = writeln!(
+                out,
+                "lazymc_handoff_seconds_count{{kind=\"{kind}\"}} {}",
+                timing.count
+            );
+        }
+
+        out
+    }
+}
+
+/// Snapshot of a counter.
+#[derive(Debug, Clone, Default)]
+pub struct CounterSnapshot {
+    /// Total count.
+    pub total: u64,
+
+    /// Count for each client protocol version, `None` if unknown.
+    pub by_protocol: BTreeMap<Option<u32>, u64>,
+}
+
+impl CounterSnapshot {
+    /// Get counts since the given earlier snapshot.
+    pub fn since(&self, earlier: &Self) -> Self {
+        Self {
+            total: self.total.saturating_sub(earlier.total),
+            by_protocol: self
+                .by_protocol
+                .iter()
+                .map(|(protocol, count)| {
+                    let earlier = earlier.by_protocol.get(protocol).copied().unwrap_or(0);
+                    (*protocol, count.saturating_sub(earlier))
+                })
+                .filter(|(_, count)| *count > 0)
+                .collect(),
+        }
+    }
+}
+
+impl fmt::Display for CounterSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.total)?;
+        if self.total == 0 {
+            return Ok(());
+        }
+
+        let tags = self
+            .by_protocol
+            .iter()
+            .map(|(protocol, count)| match protocol {
+                Some(protocol) => format!("{protocol}: {count}"),
+                None => format!("unknown: {count}"),
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(f, " (protocol {tags})")
+    }
+}
+
+/// Snapshot of a timing.
+#[derive(Debug, Clone, Default)]
+pub struct TimingSnapshot {
+    /// Number of events.
+    pub count: u64,
+
+    /// Total duration of all events.
+    pub total: Duration,
+
+    /// Longest duration of a single event.
+    pub max: Duration,
+}
+
+impl TimingSnapshot {
+    /// Get timing since the given earlier snapshot, keeps the maximum.
+    pub fn since(&self, earlier: &Self) -> Self {
+        Self {
+            count: self.count.saturating_sub(earlier.count),
+            total: self.total.saturating_sub(earlier.total),
+            max: self.max,
+        }
+    }
+}
+
+impl fmt::Display for TimingSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.count)?;
+        if self.count == 0 {
+            return Ok(());
+        }
+
+        let avg = self.total / self.count as u32;
+        write!(
+            f,
+            " (avg {}ms, max {}ms)",
+            avg.as_millis(),
+            self.max.as_millis()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counter_by_protocol() {
+        let counter = Counter::default();
+        counter.incr(Some(761));
+        counter.incr(Some(761));
+        counter.incr(None);
+
+        let snapshot = counter.snapshot();
+        assert_eq!(snapshot.total, 3);
+        assert_eq!(snapshot.by_protocol.get(&Some(761)), Some(&2));
+        assert_eq!(snapshot.by_protocol.get(&None), Some(&1));
+        assert_eq!(counter.snapshot().total, 3);
+    }
+
+    #[test]
+    fn counter_since() {
+        let counter = Counter::default();
+        counter.incr(Some(761));
+        counter.incr(None);
+        let earlier = counter.snapshot();
+        counter.incr(Some(761));
+        counter.incr(Some(765));
+
+        let since = counter.snapshot().since(&earlier);
+        assert_eq!(since.total, 2);
+        assert_eq!(since.by_protocol.get(&Some(761)), Some(&1));
+        assert_eq!(since.by_protocol.get(&Some(765)), Some(&1));
+        assert_eq!(since.by_protocol.get(&None), None);
+        assert_eq!(counter.snapshot().since(&counter.snapshot()).total, 0);
+    }
+
+    #[test]
+    fn prometheus_format() {
+        let metrics = Metrics::default();
+        metrics.handshake_errors.incr(Some(765));
+        metrics.handshake_errors.incr(None);
+        metrics.handoff_started.record(Duration::from_millis(1500));
+
+        let out = metrics.snapshot().prometheus();
+        assert!(out.contains("# TYPE lazymc_handshake_errors_total counter\n"));
+        assert!(out.contains("lazymc_handshake_errors_total{protocol=\"765\"} 1\n"));
+        assert!(out.contains("lazymc_handshake_errors_total{protocol=\"unknown\"} 1\n"));
+        assert!(out.contains("lazymc_login_start_errors_total 0\n"));
+        assert!(out.contains("lazymc_handoff_seconds_sum{kind=\"started\"} 1.5\n"));
+        assert!(out.contains("lazymc_handoff_seconds_count{kind=\"started\"} 1\n"));
+        assert!(out.contains("lazymc_handoff_seconds_count{kind=\"cold_started\"} 0\n"));
+    }
+
+    #[test]
+    fn counter_bounds_protocol_tags() {
+        let counter = Counter::default();
+        for protocol in 0..1000 {
+            counter.incr(Some(protocol));
+        }
+        counter.incr(Some(0));
+
+        let snapshot = counter.snapshot();
+        assert_eq!(snapshot.total, 1001);
+        assert_eq!(snapshot.by_protocol.len(), MAX_PROTOCOL_TAGS);
+        assert_eq!(snapshot.by_protocol.get(&Some(0)), Some(&2));
+        assert_eq!(
+            snapshot.by_protocol.get(&None),
+            Some(&(1000 - MAX_PROTOCOL_TAGS as u64 + 1))
+        );
+    }
+}
@@ -15,10 +15,15 @@ use tokio::net::TcpStream;
 use tokio::time;
 
 use crate::config::Config;
+use crate::mc::favicon;
+use crate::net;
 use crate::proto::client::{Client, ClientState};
+use crate::proto::packet::RawPacket;
+use crate::proto::packets::login_start::LoginStartData;
 use crate::proto::{packet, packets};
 use crate::proxy;
 use crate::server::{Server, State};
+use crate::status::{self, StatusFit};
 
 /// Monitor ping inverval in seconds.
 const MONITOR_POLL_INTERVAL: Duration = Duration::from_secs(2);
@@ -29,27 +34,45 @@ const STATUS_TIMEOUT: u64 = 20;
 /// Ping request timeout in seconds.
 const PING_TIMEOUT: u64 = 10;
 
+/// Warm ping login response timeout in seconds.
+const WARM_PING_TIMEOUT: u64 = 10;
+
+/// Username used for warm ping logins.
+const WARM_PING_USERNAME: &str = "lazymc_warmup";
+
 /// Monitor server.
 pub async fn monitor_server(config: Arc<Config>, server: Arc<Server>) {
-    // Server address
-    let addr = config.server.address;
-
     let mut poll_interval = time::interval(MONITOR_POLL_INTERVAL);
+    let mut status_fit = StatusFit::default();
+    let mut last_favicon = None;
 
     loop {
         poll_interval.tick().await;
 
+        // Server addresses
+        let addrs = server.poll_addresses(&config);
+
         // Poll server state and update internal status
-        trace!(target: "lazymc::monitor", "Fetching status for {} ... ", addr);
-        let status = poll_server(&config, &server, addr).await;
+        trace!(target: "lazymc::monitor", "Fetching status for {:?} ... ", addrs);
+        let status = poll_server(&config, &server, &addrs).await;
         match status {
-            // Got status, update
-            Ok(Some(status)) => server.update_status(&config, Some(status)).await,
+            // Got status, validate favicon and fit to maximum size once when caching, update
+            Ok(Some(mut status)) => {
+                drop_invalid_favicon(&mut status, &mut last_favicon);
+                let fit = status::fit_status_size(&mut status, &config);
+                if !fit.fits() && fit != status_fit {
+                    fit.warn(&config);
+                }
+                status_fit = fit;
+                server.update_status(&config, Some(status)).await
+            }
 
             // Error, reset status
             Err(_) => server.update_status(&config, None).await,
 
-            // Didn't get status, but ping fallback worked, leave as-is, show warning
+            // Didn't get valid status, but ping fallback worked, leave as-is, show warning
+            // Invalid status responses are logged when polling already
+            Ok(None) if server.is_status_invalid() => {}
             Ok(None) => {
                 warn!(target: "lazymc::monitor", "Failed to poll server status, ping fallback succeeded");
             }
@@ -61,6 +84,13 @@ pub async fn monitor_server(config: Arc<Config>, server: Arc<Server>) {
             server.stop(&config).await;
         }
 
+        // Stop frozen server when it has been frozen for long enough
+        #[cfg(unix)]
+        if server.should_stop_frozen(&config).await {
+            info!(target: "lazymc::monitor", "Server has been frozen for a while, stopping...");
+            server.stop_frozen(&config).await;
+        }
+
         // Check whether we should force kill server
         if server.should_kill().await {
             error!(target: "lazymc::monitor", "Force killing server, took too long to start or stop");
@@ -71,53 +101,120 @@ pub async fn monitor_server(config: Arc<Config>, server: Arc<Server>) {
     }
 }
 
+/// Drop favicon from server status if it isn't a valid 64x64 PNG image.
+///
+/// Some modded servers send odd data. Each favicon is validated once, `last` remembers the last
+/// favicon and whether it was valid. Warns once when the server sends an invalid favicon.
+fn drop_invalid_favicon(status: &mut ServerStatus, last: &mut Option<(String, bool)>) {
+    let icon = match status.favicon.take() {
+        Some(icon) => icon,
+        None => return,
+    };
+
+    let valid = match last {
+        Some((last, valid)) if *last == icon => *valid,
+        _ => {
+            let valid = favicon::is_valid_favicon(&icon);
+            if !valid {
+                warn!(target: "lazymc::monitor", "Server sent invalid favicon, must be 64x64 PNG image, dropping it");
+            }
+            *last = Some((icon.clone(), valid));
+            valid
+        }
+    };
+
+    if valid {
+        status.favicon = Some(icon);
+    }
+}
+
 /// Poll server state.
 ///
 /// Returns `Ok` if status/ping succeeded, includes server status most of the time.
 /// Returns `Err` if no connection could be established or if an error occurred.
+///
+/// An invalid status response never replaces the last known status, the ping fallback is used
+/// instead. Warns once when the server starts sending invalid status responses, repeats are
+/// logged at debug level. Server addresses are tried in order, the first reachable one is used.
 pub async fn poll_server(
     config: &Config,
     server: &Server,
-    addr: SocketAddr,
+    addrs: &[SocketAddr],
 ) -> Result<Option<ServerStatus>, ()> {
     // Fetch status
-    if let Ok(status) = fetch_status(config, addr).await {
-        return Ok(Some(status));
+    match fetch_status(config, addrs).await {
+        Ok(status) => {
+            if server.set_status_invalid(false) {
+                info!(target: "lazymc::monitor", "Server sent valid status response again");
+            }
+            return Ok(Some(status));
+        }
+
+        // Server is reachable but sent a bad response, keep last known status
+        Err(StatusError::Invalid(reason)) => {
+            if server.set_status_invalid(true) {
+                debug!(target: "lazymc::monitor", "Server sent invalid status response ({}), keeping last known status", reason);
+            } else {
+                warn!(target: "lazymc::monitor", "Server sent invalid status response ({}), keeping last known status", reason);
+            }
+        }
+
+        Err(StatusError::Failed) => {}
     }
 
     // Try ping fallback if server is currently started
     if server.state() == State::Started {
         debug!(target: "lazymc::monitor", "Failed to get status from started server, trying ping...");
-        do_ping(config, addr).await?;
+        do_ping(config, addrs).await?;
+        return Ok(None);
     }
 
     Err(())
 }
 
+/// Status poll error.
+enum StatusError {
+    /// Failed to get a status response.
+    Failed,
+
+    /// Server sent an invalid status response, with reason.
+    Invalid(&'static str),
+}
+
 /// Attemp to fetch status from server.
-async fn fetch_status(config: &Config, addr: SocketAddr) -> Result<ServerStatus, ()> {
-    let mut stream = TcpStream::connect(addr).await.map_err(|_| ())?;
+async fn fetch_status(config: &Config, addrs: &[SocketAddr]) -> Result<ServerStatus, StatusError> {
+    let mut stream = net::connect(addrs, config.server.connect_timeout())
+        .await
+        .map_err(|_| StatusError::Failed)?;
+    let addr = stream.peer_addr().map_err(|_| StatusError::Failed)?;
 
     // Add proxy header
     if config.server.send_proxy_v2 {
         trace!(target: "lazymc::monitor", "Sending local proxy header for server connection");
         stream
-            .write_all(&proxy::local_proxy_header().map_err(|_| ())?)
+            .write_all(&proxy::local_proxy_header().map_err(|_| StatusError::Failed)?)
             .await
-            .map_err(|_| ())?;
+            .map_err(|_| StatusError::Failed)?;
     }
 
     // Dummy client
     let client = Client::dummy();
 
-    send_handshake(&client, &mut stream, config, addr).await?;
-    request_status(&client, &mut stream).await?;
+    send_handshake(&client, &mut stream, config, addr, ClientState::Status)
+        .await
+        .map_err(|_| StatusError::Failed)?;
+    request_status(&client, &mut stream)
+        .await
+        .map_err(|_| StatusError::Failed)?;
     wait_for_status_timeout(&client, &mut stream).await
 }
 
 /// Attemp to ping server.
-async fn do_ping(config: &Config, addr: SocketAddr) -> Result<(), ()> {
-    let mut stream = TcpStream::connect(addr).await.map_err(|_| ())?;
+async fn do_ping(config: &Config, addrs: &[SocketAddr]) -> Result<(), ()> {
+    let mut stream = net::connect(addrs, config.server.connect_timeout())
+        .await
+        .map_err(|_| ())?;
+    let addr = stream.peer_addr().map_err(|_| ())?;
 
     // Add proxy header
     if config.server.send_proxy_v2 {
@@ -131,24 +228,56 @@ async fn do_ping(config: &Config, addr: SocketAddr) -> Result<(), ()> {
     // Dummy client
     let client = Client::dummy();
 
-    send_handshake(&client, &mut stream, config, addr).await?;
+    send_handshake(&client, &mut stream, config, addr, ClientState::Status).await?;
     let token = send_ping(&client, &mut stream).await?;
     wait_for_ping_timeout(&client, &mut stream, token).await
 }
 
+/// Send warm ping to server, a login aborted at the first server response.
+///
+/// Keeps the server JIT and caches warm. Returns whether the server requested encryption or a
+/// login plugin response, in which case the login never joined. Other servers, such as offline
+/// mode servers, accept the login and briefly see a player join.
+pub async fn warm_ping(config: &Config, addrs: &[SocketAddr]) -> Result<bool, ()> {
+    let mut stream = net::connect(addrs, config.server.connect_timeout())
+        .await
+        .map_err(|_| ())?;
+    let addr = stream.peer_addr().map_err(|_| ())?;
+
+    // Add proxy header
+    if config.server.send_proxy_v2 {
+        trace!(target: "lazymc::monitor", "Sending local proxy header for server connection");
+        stream
+            .write_all(&proxy::local_proxy_header().map_err(|_| ())?)
+            .await
+            .map_err(|_| ())?;
+    }
+
+    // Dummy client
+    let client = Client::dummy();
+
+    send_handshake(&client, &mut stream, config, addr, ClientState::Login).await?;
+    send_login_start(&client, &mut stream, config).await?;
+    let id = wait_for_login_response_timeout(&client, &mut stream).await?;
+
+    Ok(id == packets::login::CLIENT_ENCRYPTION_REQUEST
+        || id == packets::login::CLIENT_LOGIN_PLUGIN_REQUEST)
+}
+
 /// Send handshake.
 async fn send_handshake(
     client: &Client,
     stream: &mut TcpStream,
     config: &Config,
     addr: SocketAddr,
+    next_state: ClientState,
 ) -> Result<(), ()> {
     packet::write_packet(
         Handshake {
             protocol_version: config.public.protocol as i32,
             server_addr: addr.ip().to_string(),
             server_port: addr.port(),
-            next_state: ClientState::Status.to_id(),
+            next_state: next_state.to_id(),
         },
         client,
         &mut stream.split().1,
@@ -161,6 +290,25 @@ async fn request_status(client: &Client, stream: &mut TcpStream) -> Result<(), (
     packet::write_packet(StatusRequest {}, client, &mut stream.split().1).await
 }
 
+/// Send login start for warm ping user.
+///
+/// Sends no UUID, or the nil UUID if required, online mode servers don't use it.
+async fn send_login_start(
+    client: &Client,
+    stream: &mut TcpStream,
+    config: &Config,
+) -> Result<(), ()> {
+    let login_start = LoginStartData {
+        name: WARM_PING_USERNAME.into(),
+        uuid: None,
+        matches_protocol: true,
+    };
+    let data = login_start.encode(config.public.protocol)?;
+    let packet =
+        RawPacket::new(packets::login::SERVER_LOGIN_START, data).encode_with_len(client)?;
+    stream.write_all(&packet).await.map_err(|_| ())
+}
+
 /// Send status request.
 async fn send_ping(client: &Client, stream: &mut TcpStream) -> Result<u64, ()> {
     let token = rand::thread_rng().gen();
@@ -169,7 +317,12 @@ async fn send_ping(client: &Client, stream: &mut TcpStream) -> Result<u64, ()> {
 }
 
 /// Wait for a status response.
-async fn wait_for_status(client: &Client, stream: &mut TcpStream) -> Result<ServerStatus, ()> {
+///
+/// The response must be a well-formed status response, any other packet is invalid.
+async fn wait_for_status(
+    client: &Client,
+    stream: &mut TcpStream,
+) -> Result<ServerStatus, StatusError> {
     // Get stream reader, set up buffer
     let (mut reader, mut _writer) = stream.split();
     let mut buf = BytesMut::new();
@@ -182,26 +335,29 @@ async fn wait_for_status(client: &Client, stream: &mut TcpStream) -> Result<Serv
             Err(_) => continue,
         };
 
-        // Catch status response
-        if packet.id == packets::status::CLIENT_STATUS {
-            let status = StatusResponse::decode(&mut packet.data.as_slice()).map_err(|_| ())?;
-            return Ok(status.server_status);
+        // Server must respond with status
+        if packet.id != packets::status::CLIENT_STATUS {
+            return Err(StatusError::Invalid("unexpected packet"));
         }
+
+        return StatusResponse::decode(&mut packet.data.as_slice())
+            .map(|status| status.server_status)
+            .map_err(|_| StatusError::Invalid("malformed status"));
     }
 
     // Some error occurred
-    Err(())
+    Err(StatusError::Failed)
 }
 
 /// Wait for a status response.
 async fn wait_for_status_timeout(
     client: &Client,
     stream: &mut TcpStream,
-) -> Result<ServerStatus, ()> {
+) -> Result<ServerStatus, StatusError> {
     let status = wait_for_status(client, stream);
     tokio::time::timeout(Duration::from_secs(STATUS_TIMEOUT), status)
         .await
-        .map_err(|_| ())?
+        .map_err(|_| StatusError::Failed)?
 }
 
 /// Wait for a status response.
@@ -246,3 +402,224 @@ async fn wait_for_ping_timeout(
         .await
         .map_err(|_| ())?
 }
+
+/// Wait for first login response, returns its packet ID.
+async fn wait_for_login_response(client: &Client, stream: &mut TcpStream) -> Result<u8, ()> {
+    let (mut reader, _) = stream.split();
+    let mut buf = BytesMut::new();
+
+    match packet::read_packet(client, &mut buf, &mut reader).await {
+        Ok(Some((packet, _raw))) => Ok(packet.id),
+        Ok(None) | Err(()) => Err(()),
+    }
+}
+
+/// Wait for first login response, returns its packet ID.
+async fn wait_for_login_response_timeout(
+    client: &Client,
+    stream: &mut TcpStream,
+) -> Result<u8, ()> {
+    let response = wait_for_login_response(client, stream);
+    tokio::time::timeout(Duration::from_secs(WARM_PING_TIMEOUT), response)
+        .await
+        .map_err(|_| ())?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use minecraft_protocol::data::chat::{Message, Payload};
+    use minecraft_protocol::data::server_status::{OnlinePlayers, ServerVersion};
+    use minecraft_protocol::encoder::Encoder;
+    use tokio::net::TcpListener;
+    use tokio::task::JoinHandle;
+
+    use crate::proto::client::ClientInfo;
+    use crate::types;
+
+    /// Spawn fake server, reading the given number of packets and responding with the given data.
+    ///
+    /// Returns config to connect to it, and a handle resolving to the received packets.
+    async fn fake_server(
+        packets: usize,
+        response: Vec<u8>,
+    ) -> (Config, JoinHandle<Vec<RawPacket>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config = toml::from_str(&format!(
+            "[server]\ncommand = \"true\"\naddress = \"{}\"",
+            listener.local_addr().unwrap()
+        ))
+        .unwrap();
+
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let (mut reader, mut writer) = stream.split();
+            let client = Client::dummy();
+            let mut buf = BytesMut::new();
+            let mut received = vec![];
+            for _ in 0..packets {
+                let (packet, _) = packet::read_packet(&client, &mut buf, &mut reader)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                received.push(packet);
+            }
+            writer.write_all(&response).await.unwrap();
+
+            // Wait for client to close
+            let _ = packet::read_packet(&client, &mut buf, &mut reader).await;
+            received
+        });
+
+        (config, handle)
+    }
+
+    /// Encode raw packet with length prefix.
+    fn packet(id: u8, data: &[u8]) -> Vec<u8> {
+        RawPacket::new(id, data.to_vec())
+            .encode_with_len(&Client::dummy())
+            .unwrap()
+    }
+
+    /// Server status with one player online.
+    fn online_status() -> ServerStatus {
+        ServerStatus {
+            version: ServerVersion {
+                name: "1.20.4".into(),
+                protocol: 765,
+            },
+            description: Message::new(Payload::text("A Minecraft Server")),
+            players: OnlinePlayers {
+                online: 1,
+                max: 20,
+                sample: vec![],
+            },
+            favicon: None,
+        }
+    }
+
+    /// Encode status response packet, with one player online.
+    fn status_response() -> Vec<u8> {
+        let server_status = online_status();
+        let mut data = vec![];
+        StatusResponse { server_status }.encode(&mut data).unwrap();
+        packet(packets::status::CLIENT_STATUS, &data)
+    }
+
+    #[tokio::test]
+    async fn fetch_valid_status() {
+        let (config, server) = fake_server(2, status_response()).await;
+        let status = fetch_status(&config, &config.server.addresses).await;
+        assert!(matches!(status, Ok(status) if status.players.online == 1));
+
+        let received = server.await.unwrap();
+        let handshake = Handshake::decode(&mut received[0].data.as_slice()).unwrap();
+        assert_eq!(handshake.next_state, ClientState::Status.to_id());
+        assert_eq!(received[1].id, packets::status::SERVER_STATUS);
+    }
+
+    #[tokio::test]
+    async fn fetch_malformed_status() {
+        // Status response packet with data that isn't status JSON
+        let mut data = types::encode_var_int(8).unwrap();
+        data.extend(b"not json");
+        for response in [
+            packet(packets::status::CLIENT_STATUS, &data),
+            packet(packets::status::CLIENT_STATUS, &[]),
+        ] {
+            let (config, server) = fake_server(2, response).await;
+            let status = fetch_status(&config, &config.server.addresses).await;
+            assert!(matches!(
+                status,
+                Err(StatusError::Invalid("malformed status"))
+            ));
+            server.await.unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn fetch_unexpected_packet() {
+        let (config, server) = fake_server(2, packet(0x05, &[0])).await;
+        let status = fetch_status(&config, &config.server.addresses).await;
+        assert!(matches!(
+            status,
+            Err(StatusError::Invalid("unexpected packet"))
+        ));
+        server.await.unwrap();
+    }
+
+    #[test]
+    fn invalid_favicon_dropped() {
+        let mut png = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        png.extend(64u32.to_be_bytes());
+        png.extend(64u32.to_be_bytes());
+        let valid = favicon::encode_favicon(&png);
+        let invalid = "data:image/png;base64,bm90IGEgcG5n".to_string();
+        let mut status = online_status();
+        let mut last = None;
+
+        // Valid favicon is kept
+        status.favicon = Some(valid.clone());
+        drop_invalid_favicon(&mut status, &mut last);
+        assert_eq!(status.favicon.as_ref(), Some(&valid));
+        assert_eq!(last, Some((valid, true)));
+
+        // Invalid favicon is dropped, also when sent again
+        for _ in 0..2 {
+            status.favicon = Some(invalid.clone());
+            drop_invalid_favicon(&mut status, &mut last);
+            assert_eq!(status.favicon, None);
+            assert_eq!(last, Some((invalid.clone(), false)));
+        }
+    }
+
+    #[tokio::test]
+    async fn poll_invalid_status_tracked() {
+        let server = Server::default();
+
+        // Invalid responses, repeatedly
+        for _ in 0..2 {
+            let (config, fake) = fake_server(2, packet(0x05, &[0])).await;
+            let status = poll_server(&config, &server, &config.server.addresses).await;
+            assert!(status.is_err());
+            assert!(server.is_status_invalid());
+            fake.await.unwrap();
+        }
+
+        // Recovers on valid response
+        let (config, fake) = fake_server(2, status_response()).await;
+        let status = poll_server(&config, &server, &config.server.addresses).await;
+        assert!(matches!(status, Ok(Some(_))));
+        assert!(!server.is_status_invalid());
+        fake.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn warm_ping_online_mode() {
+        let response = packet(packets::login::CLIENT_ENCRYPTION_REQUEST, &[0]);
+        let (config, server) = fake_server(2, response).await;
+        assert_eq!(warm_ping(&config, &config.server.addresses).await, Ok(true));
+
+        let received = server.await.unwrap();
+        let handshake = Handshake::decode(&mut received[0].data.as_slice()).unwrap();
+        assert_eq!(handshake.next_state, ClientState::Login.to_id());
+        assert_eq!(received[1].id, packets::login::SERVER_LOGIN_START);
+
+        let mut client_info = ClientInfo::empty();
+        client_info.protocol = Some(config.public.protocol);
+        let login_start = LoginStartData::decode(&client_info, &received[1].data).unwrap();
+        assert_eq!(login_start.name, WARM_PING_USERNAME);
+        assert!(login_start.matches_protocol);
+    }
+
+    #[tokio::test]
+    async fn warm_ping_offline_mode() {
+        let response = packet(packets::login::CLIENT_LOGIN_SUCCESS, &[0]);
+        let (config, server) = fake_server(2, response).await;
+        assert_eq!(
+            warm_ping(&config, &config.server.addresses).await,
+            Ok(false)
+        );
+        server.await.unwrap();
+    }
+}
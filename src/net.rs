@@ -1,7 +1,58 @@
+use std::collections::HashMap;
 use std::error::Error;
 use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
 use tokio::io::AsyncWriteExt;
 use tokio::net::TcpStream;
+use tokio::time;
+
+/// Time to cache reverse DNS lookup results for.
+const REVERSE_DNS_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Maximum number of reverse DNS lookup results to cache.
+const REVERSE_DNS_MAX_ENTRIES: usize = 1024;
+
+/// Maximum number of concurrent reverse DNS lookups.
+const REVERSE_DNS_MAX_LOOKUPS: usize = 8;
+
+/// Connect to the first reachable address of the given list.
+///
+/// Addresses are tried in order. If none is reachable, the error of the last attempt is returned.
+/// If a timeout is given, each connection attempt fails after that time.
+pub async fn connect(
+    addrs: &[SocketAddr],
+    timeout: Option<Duration>,
+) -> Result<TcpStream, io::Error> {
+    let mut last_err = None;
+
+    for addr in addrs {
+        let result = match timeout {
+            Some(timeout) => time::timeout(timeout, TcpStream::connect(*addr))
+                .await
+                .unwrap_or_else(|_| {
+                    Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "connection timed out",
+                    ))
+                }),
+            None => TcpStream::connect(*addr).await,
+        };
+        match result {
+            Ok(stream) => return Ok(stream),
+            Err(err) => {
+                debug!(target: "lazymc", "Failed to connect to {}: {}", addr, err);
+                last_err = Some(err);
+            }
+        }
+    }
+
+    Err(last_err
+        .unwrap_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no address to connect to")))
+}
 
 /// Gracefully close given TCP stream.
 ///
@@ -20,3 +71,302 @@ pub async fn close_tcp_stream_ref(stream: &mut TcpStream) -> Result<(), Box<dyn
         Err(err) => Err(err.into()),
     }
 }
+
+/// Check whether the given error is caused by the peer closing the connection.
+///
+/// Such as when a client closes the connection after reading what it needed.
+pub fn is_closed_error(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::BrokenPipe
+            | io::ErrorKind::ConnectionReset
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::UnexpectedEof
+    )
+}
+
+/// Wait until the peer closes the given TCP stream.
+///
+/// Never completes if the peer sends data, as it is not consumed.
+pub async fn wait_closed(stream: &TcpStream) {
+    let mut buf = [0; 1];
+    match stream.peek(&mut buf).await {
+        Ok(0) | Err(_) => {}
+        Ok(_) => std::future::pending().await,
+    }
+}
+
+/// Reverse DNS lookup cache.
+///
+/// Lookups happen in the background, so getting a hostname never blocks. The number of cached
+/// entries and concurrent lookups is limited, so a connection flood can't exhaust resources.
+#[derive(Debug, Default, Clone)]
+pub struct ReverseDns {
+    cache: Arc<Mutex<HashMap<IpAddr, (Option<String>, Instant)>>>,
+
+    /// Number of lookups currently in progress.
+    lookups: Arc<AtomicUsize>,
+}
+
+impl ReverseDns {
+    /// Get cached hostname for IP.
+    ///
+    /// Returns `None` if unknown, and spawns a lookup in the background if not cached. Skips the
+    /// lookup if too many are in progress already.
+    pub fn hostname(&self, ip: IpAddr) -> Option<String> {
+        let mut cache = self.cache.lock().unwrap();
+        if let Some((hostname, at)) = cache.get(&ip) {
+            if at.elapsed() < REVERSE_DNS_TTL {
+                return hostname.clone();
+            }
+        }
+
+        // Limit concurrent lookups
+        if self
+            .lookups
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |lookups| {
+                (lookups < REVERSE_DNS_MAX_LOOKUPS).then(|| lookups + 1)
+            })
+            .is_err()
+        {
+            trace!(target: "lazymc", "Skipping reverse DNS lookup for {}, too many in progress", ip);
+            return None;
+        }
+
+        // Make room if full, forget expired entries first and the oldest entry otherwise
+        if cache.len() >= REVERSE_DNS_MAX_ENTRIES {
+            cache.retain(|_, (_, at)| at.elapsed() < REVERSE_DNS_TTL);
+        }
+        if cache.len() >= REVERSE_DNS_MAX_ENTRIES {
+            if let Some(oldest) = cache
+                .iter()
+                .min_by_key(|(_, (_, at))| *at)
+                .map(|(ip, _)| *ip)
+            {
+                cache.remove(&oldest);
+            }
+        }
+
+        // Mark this one as pending to prevent duplicate lookups
+        cache.insert(ip, (None, Instant::now()));
+        drop(cache);
+
+        let cache = self.cache.clone();
+        let lookups = self.lookups.clone();
+        tokio::spawn(async move {
+            let hostname = tokio::task::spawn_blocking(move || lookup_addr(ip))
+                .await
+                .ok()
+                .flatten();
+            lookups.fetch_sub(1, Ordering::Relaxed);
+            trace!(target: "lazymc", "Reverse DNS lookup for {}: {:?}", ip, hostname);
+
+            // Cache may have been filled by others in the meantime, only update pending entry
+            if let Some(entry) = cache.lock().unwrap().get_mut(&ip) {
+                *entry = (hostname, Instant::now());
+            }
+        });
+
+        None
+    }
+}
+
+/// Look up hostname for IP through reverse DNS, blocking.
+///
+/// Returns `None` if it has no hostname. Only supported on Unix.
+#[cfg(unix)]
+fn lookup_addr(ip: IpAddr) -> Option<String> {
+    use std::ffi::CStr;
+    use std::mem;
+
+    // Maximum hostname length, see NI_MAXHOST
+    let mut host = [0 as libc::c_char; 1025];
+
+    let result = match ip {
+        IpAddr::V4(ip) => {
+            let mut addr: libc::sockaddr_in = unsafe { mem::zeroed() };
+            addr.sin_family = libc::AF_INET as libc::sa_family_t;
+            addr.sin_addr = libc::in_addr {
+                s_addr: u32::from_ne_bytes(ip.octets()),
+            };
+            #[cfg(any(target_os = "macos", target_os = "freebsd"))]
+            {
+                addr.sin_len = mem::size_of_val(&addr) as u8;
+            }
+            unsafe {
+                libc::getnameinfo(
+                    &addr as *const _ as *const libc::sockaddr,
+                    mem::size_of_val(&addr) as libc::socklen_t,
+                    host.as_mut_ptr(),
+                    host.len() as libc::socklen_t,
+                    std::ptr::null_mut(),
+                    0,
+                    libc::NI_NAMEREQD,
+                )
+            }
+        }
+        IpAddr::V6(ip) => {
+            let mut addr: libc::sockaddr_in6 = unsafe { mem::zeroed() };
+            addr.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+            addr.sin6_addr = libc::in6_addr {
+                s6_addr: ip.octets(),
+            };
+            #[cfg(any(target_os = "macos", target_os = "freebsd"))]
+            {
+                addr.sin6_len = mem::size_of_val(&addr) as u8;
+            }
+            unsafe {
+                libc::getnameinfo(
+                    &addr as *const _ as *const libc::sockaddr,
+                    mem::size_of_val(&addr) as libc::socklen_t,
+                    host.as_mut_ptr(),
+                    host.len() as libc::socklen_t,
+                    std::ptr::null_mut(),
+                    0,
+                    libc::NI_NAMEREQD,
+                )
+            }
+        }
+    };
+
+    if result != 0 {
+        return None;
+    }
+
+    unsafe { CStr::from_ptr(host.as_ptr()) }
+        .to_str()
+        .ok()
+        .map(|host| host.to_string())
+}
+
+/// Look up hostname for IP through reverse DNS, blocking.
+///
+/// Returns `None` if it has no hostname. Only supported on Unix.
+#[cfg(not(unix))]
+fn lookup_addr(_ip: IpAddr) -> Option<String> {
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::{TcpListener, TcpSocket};
+
+    /// Timeout for connection attempts that are expected to time out.
+    const CONNECT_TIMEOUT: Duration = Duration::from_millis(200);
+
+    /// Get address nothing is listening on, connecting to it is refused.
+    async fn refused_addr() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap()
+    }
+
+    /// Get address of listener with full backlog, connecting to it doesn't complete.
+    ///
+    /// Returned listener and streams must be kept alive.
+    async fn unresponsive_addr() -> (TcpListener, Vec<TcpStream>, SocketAddr) {
+        let socket = TcpSocket::new_v4().unwrap();
+        socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let listener = socket.listen(1).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // Fill backlog, further connection attempts are dropped
+        let mut streams = vec![];
+        for _ in 0..64 {
+            match time::timeout(CONNECT_TIMEOUT, TcpStream::connect(addr)).await {
+                Ok(Ok(stream)) => streams.push(stream),
+                _ => break,
+            }
+        }
+
+        (listener, streams, addr)
+    }
+
+    #[tokio::test]
+    async fn connect_failover() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let stream = connect(&[refused_addr().await, addr], None).await.unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+
+        // Fails over after timeout
+        let (_listener, _streams, unresponsive) = unresponsive_addr().await;
+        let stream = connect(&[unresponsive, addr], Some(CONNECT_TIMEOUT))
+            .await
+            .unwrap();
+        assert_eq!(stream.peer_addr().unwrap(), addr);
+    }
+
+    #[tokio::test]
+    async fn connect_timeout() {
+        let (_listener, _streams, addr) = unresponsive_addr().await;
+
+        let start = Instant::now();
+        let err = connect(&[addr], Some(CONNECT_TIMEOUT)).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() >= CONNECT_TIMEOUT);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn connect_unreachable() {
+        let addrs = [refused_addr().await, refused_addr().await];
+        let err = connect(&addrs, None).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::ConnectionRefused);
+
+        let err = connect(&[], None).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn closed_errors() {
+        for kind in [
+            io::ErrorKind::BrokenPipe,
+            io::ErrorKind::ConnectionReset,
+            io::ErrorKind::ConnectionAborted,
+            io::ErrorKind::UnexpectedEof,
+        ] {
+            assert!(is_closed_error(&io::Error::from(kind)), "{kind:?}");
+        }
+
+        for kind in [
+            io::ErrorKind::TimedOut,
+            io::ErrorKind::ConnectionRefused,
+            io::ErrorKind::Other,
+        ] {
+            assert!(!is_closed_error(&io::Error::from(kind)), "{kind:?}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn lookup_loopback() {
+        let hostname = lookup_addr(IpAddr::from([127, 0, 0, 1])).unwrap();
+        assert!(!hostname.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn reverse_dns_cached() {
+        let dns = ReverseDns::default();
+        let ip = IpAddr::from([127, 0, 0, 1]);
+
+        // Unknown at first, looked up in the background
+        assert_eq!(dns.hostname(ip), None);
+        let hostname = time::timeout(Duration::from_secs(5), async {
+            loop {
+                if dns.lookups.load(Ordering::Relaxed) == 0 {
+                    if let Some(hostname) = dns.hostname(ip) {
+                        return hostname;
+                    }
+                }
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(Some(hostname), lookup_addr(ip));
+        assert_eq!(dns.lookups.load(Ordering::Relaxed), 0);
+    }
+}
@@ -1,9 +1,30 @@
 #[cfg(windows)]
 pub mod windows;
 
+#[cfg(target_os = "linux")]
+use std::ffi::{CStr, CString};
+#[cfg(target_os = "linux")]
+use std::fs;
+#[cfg(target_os = "linux")]
+use std::mem;
+#[cfg(target_os = "linux")]
+use std::os::unix::ffi::OsStringExt;
+#[cfg(target_os = "linux")]
+use std::path::{Path, PathBuf};
+#[cfg(target_os = "linux")]
+use std::sync::Mutex;
+
 #[cfg(unix)]
 use nix::{sys::signal, unistd::Pid};
 
+/// Root of the cgroup filesystem.
+#[cfg(target_os = "linux")]
+const CGROUP_ROOT: &str = "/sys/fs/cgroup";
+
+/// CPU affinity of lazymc before pinning it to configured cores.
+#[cfg(target_os = "linux")]
+static ORIGINAL_CPU_AFFINITY: Mutex<Option<CpuSet>> = Mutex::new(None);
+
 /// Force kill process.
 ///
 /// Results in undefined behavior if PID is invalid.
@@ -65,6 +86,166 @@ pub fn unfreeze(pid: u32) -> bool {
     );
 }
 
+/// Set niceness of the current process.
+///
+/// Only calls `setpriority`, so it is safe to use in a forked child process before exec.
+#[cfg(unix)]
+pub fn set_nice_self(nice: i32) -> bool {
+    unsafe { libc::setpriority(libc::PRIO_PROCESS, 0, nice) == 0 }
+}
+
+/// Get niceness of process.
+#[cfg(unix)]
+pub fn nice(pid: u32) -> i32 {
+    unsafe { libc::getpriority(libc::PRIO_PROCESS, pid as libc::id_t) }
+}
+
+/// Get path of the `cgroup.procs` file of the given cgroup.
+///
+/// The cgroup may be given relative to the cgroup filesystem root, or as absolute path.
+#[cfg(target_os = "linux")]
+fn cgroup_procs_path(cgroup: &str) -> PathBuf {
+    let dir = if Path::new(cgroup).starts_with(CGROUP_ROOT) {
+        PathBuf::from(cgroup)
+    } else {
+        Path::new(CGROUP_ROOT).join(cgroup.trim_start_matches('/'))
+    };
+    dir.join("cgroup.procs")
+}
+
+/// Get path of the `cgroup.procs` file of the given cgroup, for use with `set_cgroup_self`.
+///
+/// Returns `None` if the path contains a NUL byte.
+#[cfg(target_os = "linux")]
+pub fn cgroup_procs(cgroup: &str) -> Option<CString> {
+    CString::new(cgroup_procs_path(cgroup).into_os_string().into_vec()).ok()
+}
+
+/// Move process into the given cgroup.
+///
+/// The cgroup may be given relative to the cgroup filesystem root, or as absolute path.
+#[cfg(target_os = "linux")]
+pub fn set_cgroup(pid: u32, cgroup: &str) -> bool {
+    let path = cgroup_procs_path(cgroup);
+    match fs::write(&path, pid.to_string()) {
+        Ok(()) => true,
+        Err(err) => {
+            warn!(target: "lazymc", "Failed to move server process into cgroup {}: {err}", path.display());
+            false
+        }
+    }
+}
+
+/// Move current process into cgroup, given its `cgroup.procs` file from `cgroup_procs`.
+///
+/// Only calls `getpid`, `open`, `write` and `close` without allocating, so it is safe to use in a
+/// forked child process before exec.
+#[cfg(target_os = "linux")]
+pub fn set_cgroup_self(procs: &CStr) -> bool {
+    // Format PID on the stack
+    let mut buf = [0u8; 10];
+    let mut pid = unsafe { libc::getpid() } as u32;
+    let mut start = buf.len();
+    loop {
+        start -= 1;
+        buf[start] = b'0' + (pid % 10) as u8;
+        pid /= 10;
+        if pid == 0 {
+            break;
+        }
+    }
+    let len = buf.len() - start;
+
+    unsafe {
+        let fd = libc::open(procs.as_ptr(), libc::O_WRONLY | libc::O_CLOEXEC);
+        if fd < 0 {
+            return false;
+        }
+        let written = libc::write(fd, buf[start..].as_ptr() as *const libc::c_void, len);
+        libc::close(fd);
+        written == len as isize
+    }
+}
+
+/// Check whether process is in the given cgroup.
+#[cfg(target_os = "linux")]
+pub fn in_cgroup(pid: u32, cgroup: &str) -> bool {
+    let procs = cgroup_procs_path(cgroup);
+    let relative = match procs
+        .parent()
+        .and_then(|dir| dir.strip_prefix(CGROUP_ROOT).ok())
+    {
+        Some(relative) => relative,
+        None => return false,
+    };
+
+    // Unified cgroup v2 hierarchy entry looks like `0::/minecraft`
+    fs::read_to_string(format!("/proc/{pid}/cgroup"))
+        .map(|data| {
+            data.lines()
+                .filter_map(|line| line.strip_prefix("0::"))
+                .any(|path| Path::new(path.trim_start_matches('/')) == relative)
+        })
+        .unwrap_or(false)
+}
+
+/// CPU affinity mask.
+#[cfg(target_os = "linux")]
+#[derive(Clone, Copy)]
+pub struct CpuSet(libc::cpu_set_t);
+
+#[cfg(target_os = "linux")]
+impl CpuSet {
+    /// Build CPU set from list of CPU core indices.
+    pub fn from_cores(cores: &[usize]) -> Self {
+        let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+        for core in cores
+            .iter()
+            .filter(|core| **core < libc::CPU_SETSIZE as usize)
+        {
+            unsafe { libc::CPU_SET(*core, &mut set) };
+        }
+        Self(set)
+    }
+
+    /// Get CPU affinity of current thread.
+    pub fn current() -> Option<Self> {
+        let mut set: libc::cpu_set_t = unsafe { mem::zeroed() };
+        let result = unsafe { libc::sched_getaffinity(0, mem::size_of_val(&set), &mut set) };
+        (result == 0).then(|| Self(set))
+    }
+
+    /// Set CPU affinity of current thread.
+    ///
+    /// Only calls `sched_setaffinity`, so it is safe to use in a forked child process before exec.
+    pub fn apply(&self) -> bool {
+        unsafe { libc::sched_setaffinity(0, mem::size_of_val(&self.0), &self.0) == 0 }
+    }
+}
+
+/// Pin current thread to the given CPU cores.
+///
+/// The first time, the original CPU affinity is remembered, see `original_cpu_affinity`.
+#[cfg(target_os = "linux")]
+pub fn set_cpu_affinity(cores: &[usize]) -> bool {
+    {
+        let mut original = ORIGINAL_CPU_AFFINITY.lock().unwrap();
+        if original.is_none() {
+            *original = CpuSet::current();
+        }
+    }
+
+    CpuSet::from_cores(cores).apply()
+}
+
+/// Get CPU affinity from before lazymc was pinned to configured cores.
+///
+/// Returns `None` if not pinned.
+#[cfg(target_os = "linux")]
+pub fn original_cpu_affinity() -> Option<CpuSet> {
+    *ORIGINAL_CPU_AFFINITY.lock().unwrap()
+}
+
 #[cfg(unix)]
 pub fn unix_signal(pid: u32, signal: signal::Signal) -> bool {
     return match signal::kill(Pid::from_raw(pid as i32), signal) {
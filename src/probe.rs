@@ -138,9 +138,12 @@ async fn connect_to_server_no_timeout(
 ) -> Result<Vec<Vec<u8>>, ()> {
     // Open connection
     // TODO: on connect fail, ping server and redirect to serve_status if offline
-    let mut outbound = TcpStream::connect(config.server.address)
-        .await
-        .map_err(|_| ())?;
+    let mut outbound = net::connect(
+        &server.poll_addresses(config),
+        config.server.connect_timeout(),
+    )
+    .await
+    .map_err(|_| ())?;
 
     // Construct temporary server client
     let tmp_client = match outbound.local_addr() {
@@ -157,9 +160,9 @@ async fn connect_to_server_no_timeout(
 
     // Select server address to use, add magic if Forge
     let server_addr = if config.server.forge {
-        format!("{}{}", config.server.address.ip(), forge::STATUS_MAGIC)
+        format!("{}{}", config.server.address().ip(), forge::STATUS_MAGIC)
     } else {
-        config.server.address.ip().to_string()
+        config.server.address().ip().to_string()
     };
 
     // Send handshake packet
@@ -167,7 +170,7 @@ async fn connect_to_server_no_timeout(
         Handshake {
             protocol_version: config.public.protocol as i32,
             server_addr,
-            server_port: config.server.address.port(),
+            server_port: config.server.address().port(),
             next_state: ClientState::Login.to_id(),
         },
         &tmp_client,
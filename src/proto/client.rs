@@ -135,4 +135,12 @@ impl ClientInfo {
         self.protocol
             .or_else(|| self.handshake.as_ref().map(|h| h.protocol_version as u32))
     }
+
+    /// Whether the protocol version is unknown, such as with pingers sending version -1.
+    pub fn is_protocol_unknown(&self) -> bool {
+        match self.handshake {
+            Some(ref handshake) => handshake.protocol_version < 0,
+            None => self.protocol.is_none(),
+        }
+    }
 }
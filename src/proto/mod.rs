@@ -2,6 +2,7 @@ pub mod action;
 pub mod client;
 pub mod packet;
 pub mod packets;
+pub mod peek;
 
 /// Default minecraft protocol version name.
 ///
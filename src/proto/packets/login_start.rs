@@ -0,0 +1,300 @@
+use crate::proto::client::ClientInfo;
+use crate::types;
+
+/// Protocol version of 1.19, adds optional signature data.
+const PROTOCOL_1_19: u32 = 759;
+
+/// Protocol version of 1.19.1, adds optional player UUID after signature data.
+const PROTOCOL_1_19_1: u32 = 760;
+
+/// Protocol version of 1.19.3, removes signature data.
+const PROTOCOL_1_19_3: u32 = 761;
+
+/// Protocol version of 1.20.2, player UUID is always sent.
+const PROTOCOL_1_20_2: u32 = 764;
+
+/// Data extracted from `LoginStart` packet.
+#[derive(Debug, Clone)]
+pub struct LoginStartData {
+    /// Player username.
+    pub name: String,
+
+    /// Player UUID, sent by 1.19.1 and newer clients.
+    ///
+    /// This is sent by the client and not verified.
+    pub uuid: Option<u128>,
+
+    /// Whether the packet matches the layout of the client protocol version, with all fields
+    /// valid and no data left over.
+    pub matches_protocol: bool,
+}
+
+impl LoginStartData {
+    /// Extract login start data from given packet data, based on the client protocol version.
+    ///
+    /// Fails only if the username can't be read. Fields after it are read leniently, the UUID is
+    /// `None` if they are malformed.
+    pub fn decode(client_info: &ClientInfo, data: &[u8]) -> Result<Self, ()> {
+        let mut reader = Reader(data);
+        let name = reader.string()?;
+
+        let uuid = match client_info.protocol() {
+            Some(p) if p >= PROTOCOL_1_20_2 => reader.uuid().map(Some),
+            Some(p) if p >= PROTOCOL_1_19_3 => reader.optional_uuid(),
+            Some(p) if p >= PROTOCOL_1_19_1 => reader
+                .skip_signature_data()
+                .and_then(|_| reader.optional_uuid()),
+            Some(p) if p >= PROTOCOL_1_19 => reader.skip_signature_data().map(|_| None),
+            _ => Ok(None),
+        };
+        let matches_protocol = uuid.is_ok() && reader.0.is_empty();
+        let uuid = uuid.unwrap_or_else(|_| {
+            debug!(target: "lazymc", "Failed to read player UUID from login start packet");
+            None
+        });
+
+        Ok(Self {
+            name,
+            uuid,
+            matches_protocol,
+        })
+    }
+
+    /// Encode login start packet data, in the layout of the given protocol version.
+    ///
+    /// Never includes signature data. The nil UUID is used if the protocol requires a UUID and
+    /// none is set.
+    pub fn encode(&self, protocol: u32) -> Result<Vec<u8>, ()> {
+        let mut data = types::encode_var_int(self.name.len() as i32)?;
+        data.extend(self.name.as_bytes());
+
+        let optional_uuid = |data: &mut Vec<u8>| match self.uuid {
+            Some(uuid) => {
+                data.push(1);
+                data.extend(uuid.to_be_bytes());
+            }
+            None => data.push(0),
+        };
+        match protocol {
+            p if p >= PROTOCOL_1_20_2 => data.extend(self.uuid.unwrap_or(0).to_be_bytes()),
+            p if p >= PROTOCOL_1_19_3 => optional_uuid(&mut data),
+            p if p >= PROTOCOL_1_19_1 => {
+                data.push(0);
+                optional_uuid(&mut data);
+            }
+            p if p >= PROTOCOL_1_19 => data.push(0),
+            _ => {}
+        }
+
+        Ok(data)
+    }
+}
+
+/// Simple reader over packet data.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    /// Read given number of bytes.
+    fn bytes(&mut self, len: usize) -> Result<&[u8], ()> {
+        if self.0.len() < len {
+            return Err(());
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    /// Read var-int.
+    fn var_int(&mut self) -> Result<i32, ()> {
+        let (len, value) = types::read_var_int(self.0)?;
+        self.0 = &self.0[len..];
+        Ok(value)
+    }
+
+    /// Read boolean.
+    fn bool(&mut self) -> Result<bool, ()> {
+        Ok(self.bytes(1)?[0] != 0)
+    }
+
+    /// Read var-int length prefixed byte array.
+    fn byte_array(&mut self) -> Result<&[u8], ()> {
+        let len = usize::try_from(self.var_int()?).map_err(|_| ())?;
+        self.bytes(len)
+    }
+
+    /// Read var-int length prefixed UTF-8 string.
+    fn string(&mut self) -> Result<String, ()> {
+        let data = self.byte_array()?;
+        String::from_utf8(data.to_vec()).map_err(|_| ())
+    }
+
+    /// Read UUID.
+    fn uuid(&mut self) -> Result<u128, ()> {
+        let bytes = self.bytes(16)?.try_into().map_err(|_| ())?;
+        Ok(u128::from_be_bytes(bytes))
+    }
+
+    /// Read UUID prefixed with a boolean telling whether it is set.
+    fn optional_uuid(&mut self) -> Result<Option<u128>, ()> {
+        match self.bool()? {
+            true => self.uuid().map(Some),
+            false => Ok(None),
+        }
+    }
+
+    /// Skip optional signature data, as sent by 1.19 to 1.19.2 clients.
+    ///
+    /// Consists of timestamp, public key and signature.
+    fn skip_signature_data(&mut self) -> Result<(), ()> {
+        if self.bool()? {
+            self.bytes(8)?;
+            self.byte_array()?;
+            self.byte_array()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Username `Notch`.
+    const NAME: &str = "054e6f746368";
+
+    /// UUID of `Notch`.
+    const UUID: &str = "069a79f444e94726a5befca90e38aaf5";
+
+    /// Signature data as sent by 1.19 to 1.19.2 clients, with timestamp, RSA public key and
+    /// signature.
+    const SIGNATURE_DATA: &str = concat!(
+        "010000018191",
+        "4ab000a20130819f300d06092a864886f70d010101050003818d00308189028181004420823cfde6f1c2",
+        "6b30f90ec7dd01e4887534a20f0b0d04c36ed80e71e0fd77b07670eb940bd5335f973daad8619b91ffc9",
+        "11f57cced458bbbf2ce03753c9bdfa0ff0169dc9575674066676cfb0b4eb8902c44269da1cf6ba66d3f8",
+        "b6d4b100a9ea0e755a5c2e8210242a08e7078f7f89385eb09423555182568b96e8a4fef2020301000180",
+        "023a0c9fc5afd7608437816bdd0a7309cb4a1252e4da70e6720fcaa4da1e98406c189c24279e9851d581",
+        "4204136feb5713c166b13269dd63fc35c797ff08a6cd90095066a745addb6d8831c2b0f87821142b4456",
+        "556d89aa82bcadae3a9578fa4535a414d025c24b40ae3ac127722988ba973aea8d37179706072ed33a14",
+        "607ad7523be6557b5134dec19681f4a1336aa2140d0597a3e6c8a0cc2020a2e939806ef0b6845d6a9d65",
+        "7eb8298f2de52ead74c79d15a75fa29b7dab332f7d700a7ccd258924260b0594b7fcf04e33a727585b4c",
+        "48a39c369640694810a1695b99dd50187e8120e4dc80e0e805caad5784f80cd5091fb5464046848dcbcd",
+        "582d77f803",
+    );
+
+    /// Decode hex string into bytes.
+    fn hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    /// Decode login start packet data for a client with the given protocol version.
+    fn decode(protocol: u32, data: &str) -> LoginStartData {
+        let client_info = ClientInfo {
+            protocol: Some(protocol),
+            ..ClientInfo::empty()
+        };
+        LoginStartData::decode(&client_info, &hex(data)).unwrap()
+    }
+
+    fn uuid() -> u128 {
+        u128::from_str_radix(UUID, 16).unwrap()
+    }
+
+    #[test]
+    fn decode_legacy() {
+        let login_start = decode(47, NAME);
+        assert_eq!(login_start.name, "Notch");
+        assert_eq!(login_start.uuid, None);
+        assert!(login_start.matches_protocol);
+    }
+
+    #[test]
+    fn decode_1_19() {
+        let login_start = decode(PROTOCOL_1_19, &format!("{NAME}{SIGNATURE_DATA}"));
+        assert_eq!(login_start.name, "Notch");
+        assert_eq!(login_start.uuid, None);
+        assert!(login_start.matches_protocol);
+
+        // Without signature data
+        let login_start = decode(PROTOCOL_1_19, &format!("{NAME}00"));
+        assert_eq!(login_start.name, "Notch");
+        assert!(login_start.matches_protocol);
+    }
+
+    #[test]
+    fn decode_1_19_1() {
+        let login_start = decode(PROTOCOL_1_19_1, &format!("{NAME}{SIGNATURE_DATA}01{UUID}"));
+        assert_eq!(login_start.name, "Notch");
+        assert_eq!(login_start.uuid, Some(uuid()));
+        assert!(login_start.matches_protocol);
+
+        // Without signature data and UUID
+        let login_start = decode(PROTOCOL_1_19_1, &format!("{NAME}0000"));
+        assert_eq!(login_start.name, "Notch");
+        assert_eq!(login_start.uuid, None);
+        assert!(login_start.matches_protocol);
+    }
+
+    #[test]
+    fn decode_1_19_3() {
+        for protocol in PROTOCOL_1_19_3..PROTOCOL_1_20_2 {
+            let login_start = decode(protocol, &format!("{NAME}01{UUID}"));
+            assert_eq!(login_start.name, "Notch");
+            assert_eq!(login_start.uuid, Some(uuid()));
+            assert!(login_start.matches_protocol);
+        }
+    }
+
+    #[test]
+    fn decode_1_20_2() {
+        for protocol in [PROTOCOL_1_20_2, 765, 766, 767] {
+            let login_start = decode(protocol, &format!("{NAME}{UUID}"));
+            assert_eq!(login_start.name, "Notch");
+            assert_eq!(login_start.uuid, Some(uuid()));
+            assert!(login_start.matches_protocol);
+        }
+    }
+
+    #[test]
+    fn decode_lenient() {
+        // Truncated UUID, username is still read
+        let login_start = decode(PROTOCOL_1_20_2, &format!("{NAME}069a79f4"));
+        assert_eq!(login_start.name, "Notch");
+        assert_eq!(login_start.uuid, None);
+        assert!(!login_start.matches_protocol);
+
+        // Username can't be read
+        let client_info = ClientInfo::empty();
+        assert!(LoginStartData::decode(&client_info, &[]).is_err());
+        assert!(LoginStartData::decode(&client_info, &hex("054e6f")).is_err());
+    }
+
+    #[test]
+    fn encode_roundtrip() {
+        for protocol in [
+            47,
+            PROTOCOL_1_19,
+            PROTOCOL_1_19_1,
+            PROTOCOL_1_19_3,
+            PROTOCOL_1_20_2,
+        ] {
+            let login_start = LoginStartData {
+                name: "Notch".into(),
+                uuid: (protocol >= PROTOCOL_1_19_1).then(uuid),
+                matches_protocol: true,
+            };
+            let data = login_start.encode(protocol).unwrap();
+            let client_info = ClientInfo {
+                protocol: Some(protocol),
+                ..ClientInfo::empty()
+            };
+            let decoded = LoginStartData::decode(&client_info, &data).unwrap();
+            assert_eq!(decoded.name, "Notch");
+            assert_eq!(decoded.uuid, login_start.uuid);
+            assert!(decoded.matches_protocol);
+        }
+    }
+}
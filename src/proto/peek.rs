@@ -0,0 +1,206 @@
+use std::time::Duration;
+
+use tokio::net::TcpStream;
+use tokio::time;
+
+use crate::proto::client::ClientState;
+use crate::proto::packets;
+use crate::types;
+
+/// Maximum number of bytes to peek for a handshake.
+///
+/// Fits a handshake with the longest allowed server address, including Forge markers.
+const PEEK_SIZE: usize = 2 * 1024;
+
+/// First byte of legacy ping packets, as sent by Minecraft 1.6 and older.
+const LEGACY_PING: u8 = 0xFE;
+
+/// Interval to peek again at if handshake is incomplete.
+const PEEK_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Handshake details peeked from a client connection.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeekedHandshake {
+    /// Client protocol version.
+    pub protocol: i32,
+
+    /// Server address client connects with, as sent by the client.
+    pub server_addr: String,
+
+    /// State client wants to switch to.
+    pub next_state: ClientState,
+}
+
+impl PeekedHandshake {
+    /// Hostname the client connects with.
+    ///
+    /// Lowercase, without Forge markers and trailing dot.
+    pub fn hostname(&self) -> String {
+        let host = self.server_addr.split('\0').next().unwrap_or_default();
+        host.trim_end_matches('.').to_ascii_lowercase()
+    }
+}
+
+/// Peek handshake from client connection, without consuming any data.
+///
+/// Returns `None` if the client doesn't send a valid handshake within the given timeout, such as
+/// legacy pings.
+pub async fn peek_handshake(stream: &TcpStream, timeout: Duration) -> Option<PeekedHandshake> {
+    let peek = async {
+        let mut buf = vec![0; PEEK_SIZE];
+        loop {
+            let len = stream.peek(&mut buf).await.ok()?;
+            if len == 0 {
+                return None;
+            }
+
+            match parse_handshake(&buf[..len]) {
+                Ok(Some(handshake)) => return Some(handshake),
+                Ok(None) if len < buf.len() => time::sleep(PEEK_RETRY_INTERVAL).await,
+                Ok(None) | Err(()) => return None,
+            }
+        }
+    };
+
+    time::timeout(timeout, peek).await.ok().flatten()
+}
+
+/// Parse handshake packet from start of the given data.
+///
+/// Returns `Ok(None)` if the data is incomplete, fails if it isn't a valid handshake.
+fn parse_handshake(data: &[u8]) -> Result<Option<PeekedHandshake>, ()> {
+    // Legacy ping, never a handshake
+    if data.first() == Some(&LEGACY_PING) {
+        return Err(());
+    }
+
+    // Packet length, incomplete if var-int isn't complete yet
+    let (read, len) = match types::read_var_int(data) {
+        Ok(result) => result,
+        Err(()) if data.len() < 5 && data.iter().all(|b| b & 0x80 != 0) => return Ok(None),
+        Err(()) => return Err(()),
+    };
+    let len = usize::try_from(len).map_err(|_| ())?;
+    let data = &data[read..];
+    if data.len() < len {
+        return Ok(None);
+    }
+    let mut data = &data[..len];
+
+    // Packet ID
+    if var_int(&mut data)? != packets::handshake::SERVER_HANDSHAKE as i32 {
+        return Err(());
+    }
+
+    // Protocol version, server address and port
+    let protocol = var_int(&mut data)?;
+    let addr_len = usize::try_from(var_int(&mut data)?).map_err(|_| ())?;
+    if data.len() < addr_len + 2 {
+        return Err(());
+    }
+    let server_addr = String::from_utf8_lossy(&data[..addr_len]).into_owned();
+    data = &data[addr_len + 2..];
+
+    // Next state, must leave handshake state
+    let next_state = match ClientState::from_id(var_int(&mut data)?) {
+        Some(state) if state != ClientState::Handshake => state,
+        _ => return Err(()),
+    };
+
+    Ok(Some(PeekedHandshake {
+        protocol,
+        server_addr,
+        next_state,
+    }))
+}
+
+/// Read var-int from start of the given data, advancing it.
+fn var_int(data: &mut &[u8]) -> Result<i32, ()> {
+    let rest: &[u8] = data;
+    let (read, value) = types::read_var_int(rest)?;
+    *data = &rest[read..];
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build handshake packet with length prefix.
+    fn handshake(protocol: i32, addr: &str, next_state: i32) -> Vec<u8> {
+        let mut packet = vec![packets::handshake::SERVER_HANDSHAKE];
+        packet.extend(types::encode_var_int(protocol).unwrap());
+        packet.extend(types::encode_var_int(addr.len() as i32).unwrap());
+        packet.extend(addr.as_bytes());
+        packet.extend(25565u16.to_be_bytes());
+        packet.extend(types::encode_var_int(next_state).unwrap());
+
+        let mut data = types::encode_var_int(packet.len() as i32).unwrap();
+        data.extend(packet);
+        data
+    }
+
+    #[test]
+    fn parse_status_and_login() {
+        assert_eq!(
+            parse_handshake(&handshake(761, "mc.example.com", 1)),
+            Ok(Some(PeekedHandshake {
+                protocol: 761,
+                server_addr: "mc.example.com".into(),
+                next_state: ClientState::Status,
+            }))
+        );
+        assert_eq!(
+            parse_handshake(&handshake(-1, "mc.example.com", 2)),
+            Ok(Some(PeekedHandshake {
+                protocol: -1,
+                server_addr: "mc.example.com".into(),
+                next_state: ClientState::Login,
+            }))
+        );
+    }
+
+    #[test]
+    fn hostname() {
+        let hostname = |addr: &str| {
+            parse_handshake(&handshake(761, addr, 2))
+                .unwrap()
+                .unwrap()
+                .hostname()
+        };
+        assert_eq!(hostname("mc.example.com"), "mc.example.com");
+        assert_eq!(hostname("MC.Example.com."), "mc.example.com");
+        assert_eq!(hostname("mc.example.com\0FML2\0"), "mc.example.com");
+        assert_eq!(hostname("127.0.0.1"), "127.0.0.1");
+    }
+
+    #[test]
+    fn parse_with_following_packet() {
+        let mut data = handshake(761, "mc.example.com", 2);
+        data.extend([3, 0, 1, b'a']);
+        assert!(matches!(parse_handshake(&data), Ok(Some(_))));
+    }
+
+    #[test]
+    fn parse_incomplete() {
+        let data = handshake(761, "mc.example.com", 1);
+        for len in 0..data.len() {
+            assert_eq!(parse_handshake(&data[..len]), Ok(None), "length {len}");
+        }
+    }
+
+    #[test]
+    fn parse_invalid() {
+        // Legacy ping
+        assert_eq!(parse_handshake(&[0xFE, 0x01, 0xFA]), Err(()));
+
+        // Status request before handshake
+        assert_eq!(parse_handshake(&[1, 0]), Err(()));
+
+        // Switching into handshake state
+        assert_eq!(
+            parse_handshake(&handshake(761, "mc.example.com", 0)),
+            Err(())
+        );
+    }
+}
@@ -1,36 +1,52 @@
+use std::collections::HashMap;
 use std::error::Error;
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use bytes::BytesMut;
 use proxy_protocol::version2::{ProxyAddresses, ProxyCommand, ProxyTransportProtocol};
 use proxy_protocol::EncodeError;
 use tokio::io;
-use tokio::io::AsyncWriteExt;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::TcpStream;
+use tokio::time;
 
+use crate::config::Config;
+use crate::metrics::Metrics;
 use crate::net;
 
+/// Buffer size for relaying client data.
+const CLIENT_COPY_BUF_SIZE: usize = 8 * 1024;
+
 /// Proxy the inbound stream to a target address.
+///
+/// Target addresses are tried in order, the first reachable one is used.
 pub async fn proxy(
     inbound: TcpStream,
     proxy_header: ProxyHeader,
-    addr_target: SocketAddr,
+    addrs_target: &[SocketAddr],
+    options: ProxyOptions,
 ) -> Result<(), Box<dyn Error>> {
-    proxy_with_queue(inbound, proxy_header, addr_target, &[]).await
+    proxy_with_queue(inbound, proxy_header, addrs_target, &[], options).await
 }
 
 /// Proxy the inbound stream to a target address.
 ///
+/// Target addresses are tried in order, the first reachable one is used.
+///
 /// Send the queue to the target server before proxying.
 pub async fn proxy_with_queue(
     inbound: TcpStream,
     proxy_header: ProxyHeader,
-    addr_target: SocketAddr,
+    addrs_target: &[SocketAddr],
     queue: &[u8],
+    options: ProxyOptions,
 ) -> Result<(), Box<dyn Error>> {
     // Set up connection to server
     // TODO: on connect fail, ping server and redirect to serve_status if offline
-    let mut outbound = TcpStream::connect(addr_target).await?;
+    let mut outbound = net::connect(addrs_target, options.connect_timeout).await?;
 
     // Add proxy header
     match proxy_header {
@@ -46,19 +62,28 @@ pub async fn proxy_with_queue(
     }
 
     // Start proxy on both streams
-    proxy_inbound_outbound_with_queue(inbound, outbound, &[], queue).await
+    proxy_inbound_outbound_with_queue(inbound, outbound, &[], queue, options).await
 }
 
 /// Proxy the inbound stream to a target address.
 ///
 /// Send the queue to the target server before proxying.
+///
+/// If an idle timeout is given, the connection is closed when the client sends nothing for that
+/// long. If activity tracking is given, the connection is tracked while open, and its activity is
+/// updated when the client sends data if configured.
 // TODO: find better name for this
 pub async fn proxy_inbound_outbound_with_queue(
     mut inbound: TcpStream,
     mut outbound: TcpStream,
     inbound_queue: &[u8],
     outbound_queue: &[u8],
+    options: ProxyOptions,
 ) -> Result<(), Box<dyn Error>> {
+    // Track open proxied connection until done
+    let open = options.activity.as_deref().map(Activity::open);
+    let activity = open.as_ref().filter(|_| options.count_activity);
+
     let (mut ri, mut wi) = inbound.split();
     let (mut ro, mut wo) = outbound.split();
 
@@ -76,8 +101,17 @@ pub async fn proxy_inbound_outbound_with_queue(
         wo.write_all(outbound_queue).await?;
     }
 
+    // Handoff is complete once queue is relayed
+    if let Some(ref handoff) = options.handoff {
+        handoff.finish();
+    }
+
     let client_to_server = async {
-        io::copy(&mut ri, &mut wo).await?;
+        if options.is_plain() {
+            io::copy(&mut ri, &mut wo).await?;
+        } else {
+            copy_client(&mut ri, &mut wo, options.idle_timeout, activity).await?;
+        }
         wo.shutdown().await
     };
     let server_to_client = async {
@@ -85,7 +119,14 @@ pub async fn proxy_inbound_outbound_with_queue(
         wi.shutdown().await
     };
 
-    tokio::try_join!(client_to_server, server_to_client)?;
+    match tokio::try_join!(client_to_server, server_to_client) {
+        Ok(_) => {}
+        Err(err) if err.kind() == io::ErrorKind::TimedOut => {
+            info!(target: "lazymc", "Closing proxied connection, client idle for too long");
+            return Ok(());
+        }
+        Err(err) => return Err(err.into()),
+    }
 
     // Gracefully close connection if not done already
     net::close_tcp_stream(inbound).await?;
@@ -93,6 +134,223 @@ pub async fn proxy_inbound_outbound_with_queue(
     Ok(())
 }
 
+/// Copy all client data from reader to writer.
+///
+/// Fails with `TimedOut` if no data is read for the given idle timeout. Updates connection
+/// activity on data if given.
+async fn copy_client<R, W>(
+    reader: &mut R,
+    writer: &mut W,
+    idle_timeout: Option<Duration>,
+    activity: Option<&OpenGuard<'_>>,
+) -> io::Result<u64>
+where
+    R: AsyncRead + Unpin + ?Sized,
+    W: AsyncWrite + Unpin + ?Sized,
+{
+    let mut buf = vec![0u8; CLIENT_COPY_BUF_SIZE];
+    let mut total = 0;
+
+    loop {
+        let read = match idle_timeout {
+            Some(timeout) => match time::timeout(timeout, reader.read(&mut buf)).await {
+                Ok(read) => read?,
+                Err(_) => return Err(io::Error::new(io::ErrorKind::TimedOut, "client idle")),
+            },
+            None => reader.read(&mut buf).await?,
+        };
+        if read == 0 {
+            return Ok(total);
+        }
+
+        if let Some(activity) = activity {
+            activity.touch();
+        }
+
+        writer.write_all(&buf[..read]).await?;
+        total += read as u64;
+    }
+}
+
+/// Options for proxied connections.
+#[derive(Debug, Clone, Default)]
+pub struct ProxyOptions {
+    /// Close connection if client sends nothing for this long.
+    pub idle_timeout: Option<Duration>,
+
+    /// Track open connections and client activity.
+    pub activity: Option<Arc<Activity>>,
+
+    /// Count client data as activity, used for `server.proxy_byte_idle`.
+    pub count_activity: bool,
+
+    /// Fail connecting to target if it takes this long.
+    pub connect_timeout: Option<Duration>,
+
+    /// Time handoff of a joining client to the server.
+    pub handoff: Option<Handoff>,
+}
+
+impl ProxyOptions {
+    /// Build proxy options for connections to the server from config.
+    pub fn from_config(config: &Config, activity: &Arc<Activity>) -> Self {
+        Self {
+            idle_timeout: match config.advanced.proxy_idle_timeout {
+                0 => None,
+                secs => Some(Duration::from_secs(secs as u64)),
+            },
+            activity: Some(activity.clone()),
+            count_activity: config.server.proxy_byte_idle > 0,
+            connect_timeout: config.server.connect_timeout(),
+            handoff: None,
+        }
+    }
+
+    /// Whether no options are set, allowing to proxy without inspecting data.
+    fn is_plain(&self) -> bool {
+        self.idle_timeout.is_none() && !self.count_activity
+    }
+}
+
+/// Activity of proxied clients.
+///
+/// Tracks when each open proxied connection last had client data, to detect zombie sessions.
+#[derive(Debug, Default)]
+pub struct Activity {
+    /// Open connections and their activity.
+    state: Mutex<ActivityState>,
+
+    /// ID of next opened connection.
+    next_id: AtomicU64,
+}
+
+/// Open proxied connections and their activity.
+#[derive(Debug, Default)]
+struct ActivityState {
+    /// Last time each open connection had client data, by connection ID.
+    connections: HashMap<u64, Instant>,
+}
+
+impl Activity {
+    /// Track open proxied connection, until the returned guard is dropped.
+    ///
+    /// Opening a connection counts as activity.
+    pub fn open(&self) -> OpenGuard<'_> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.state
+            .lock()
+            .unwrap()
+            .connections
+            .insert(id, Instant::now());
+        OpenGuard { activity: self, id }
+    }
+
+    /// Get number of open proxied connections.
+    pub fn open_count(&self) -> usize {
+        self.state.lock().unwrap().connections.len()
+    }
+
+    /// Get time since the most recently active open connection had client data.
+    ///
+    /// Returns `None` if no connection is open.
+    pub fn idle_for(&self) -> Option<Duration> {
+        let state = self.state.lock().unwrap();
+        state.connections.values().max().map(|last| last.elapsed())
+    }
+}
+
+/// Guard tracking an open proxied connection.
+pub struct OpenGuard<'a> {
+    activity: &'a Activity,
+    id: u64,
+}
+
+impl OpenGuard<'_> {
+    /// Mark client data on this connection now.
+    pub fn touch(&self) {
+        self.touch_at(Instant::now());
+    }
+
+    /// Mark client data on this connection at the given time.
+    pub fn touch_at(&self, at: Instant) {
+        if let Some(last) = self
+            .activity
+            .state
+            .lock()
+            .unwrap()
+            .connections
+            .get_mut(&self.id)
+        {
+            *last = at;
+        }
+    }
+}
+
+impl Drop for OpenGuard<'_> {
+    fn drop(&mut self) {
+        self.activity
+            .state
+            .lock()
+            .unwrap()
+            .connections
+            .remove(&self.id);
+    }
+}
+
+/// Handoff of a joining client to the server.
+///
+/// Spans connecting to the server and relaying queued client data.
+#[derive(Debug, Clone)]
+pub struct Handoff {
+    /// When the handoff started.
+    started: Instant,
+
+    /// Whether the client waited for the server to start.
+    cold_started: bool,
+
+    /// Warn if handoff takes this long.
+    slow_threshold: Option<Duration>,
+
+    /// Metrics to record handoff time in.
+    metrics: Arc<Metrics>,
+}
+
+impl Handoff {
+    /// Start timing a handoff now.
+    pub fn start(config: &Config, metrics: &Arc<Metrics>, cold_started: bool) -> Self {
+        Self {
+            started: Instant::now(),
+            cold_started,
+            slow_threshold: match config.advanced.slow_handoff_threshold {
+                0 => None,
+                millis => Some(Duration::from_millis(millis as u64)),
+            },
+            metrics: metrics.clone(),
+        }
+    }
+
+    /// Finish handoff, record time and warn if slow.
+    fn finish(&self) {
+        let elapsed = self.started.elapsed();
+        let kind = if self.cold_started {
+            self.metrics.handoff_cold_started.record(elapsed);
+            "cold started"
+        } else {
+            self.metrics.handoff_started.record(elapsed);
+            "started"
+        };
+
+        match self.slow_threshold {
+            Some(threshold) if elapsed >= threshold => {
+                warn!(target: "lazymc", "Slow handoff of client to {} server, took {}ms", kind, elapsed.as_millis())
+            }
+            _ => {
+                trace!(target: "lazymc", "Handed off client to {} server in {}ms", kind, elapsed.as_millis())
+            }
+        }
+    }
+}
+
 /// Proxy header.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ProxyHeader {
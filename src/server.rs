@@ -1,10 +1,13 @@
-use std::net::IpAddr;
-use std::sync::atomic::{AtomicU8, Ordering};
+use std::collections::{HashMap, VecDeque};
+use std::net::{IpAddr, SocketAddr};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use futures::FutureExt;
 use minecraft_protocol::data::server_status::ServerStatus;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
 use tokio::process::Command;
 use tokio::sync::watch;
 #[cfg(feature = "rcon")]
@@ -12,11 +15,17 @@ use tokio::sync::Semaphore;
 use tokio::sync::{Mutex, RwLock, RwLockReadGuard};
 use tokio::time;
 
-use crate::config::{Config, Server as ConfigServer};
-use crate::mc::ban::{BannedIp, BannedIps};
+use crate::config::{ActivitySource, AddressSelection, Config, Server as ConfigServer};
+#[cfg(feature = "lobby")]
+use crate::join::queue::Queue;
+use crate::mc::ban::{BannedIp, BannedIps, BannedPlayer, BannedPlayers};
 use crate::mc::whitelist::Whitelist;
+use crate::metrics::Metrics;
+use crate::net::ReverseDns;
 use crate::os;
 use crate::proto::packets::play::join_game::JoinGameData;
+use crate::proxy::Activity;
+use crate::status::StatusFitCache;
 
 /// Server cooldown after the process quit.
 /// Used to give it some more time to quit forgotten threads, such as for RCON.
@@ -35,6 +44,19 @@ const RCON_COOLDOWN: Duration = Duration::from_secs(15);
 /// - 130: https://unix.stackexchange.com/q/386836/61092
 const ALLOWED_EXIT_CODES: [i32; 2] = [130, 143];
 
+/// Maximum delay before restarting a crashed server.
+const CRASH_RESTART_BACKOFF_MAX: Duration = Duration::from_secs(5 * 60);
+
+/// Uptime after which a crash is not counted as consecutive crash.
+const CRASH_RESET_UPTIME: Duration = Duration::from_secs(10 * 60);
+
+/// Window to count logins in for the login throttle.
+const LOGIN_THROTTLE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Interval to check whether the server process exited at.
+#[cfg(unix)]
+const PROCESS_EXIT_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
 /// Shared server state.
 #[derive(Debug)]
 pub struct Server {
@@ -73,12 +95,59 @@ pub struct Server {
     /// Used as starting/stopping timeout.
     kill_at: RwLock<Option<Instant>>,
 
+    /// Last time the server process quit.
+    ///
+    /// Used for the restart cooldown.
+    last_stop: RwLock<Option<Instant>>,
+
+    /// Time the server was last started, or stopped.
+    ///
+    /// Used to show server uptime and sleeping time.
+    started_at: RwLock<Option<Instant>>,
+    stopped_at: RwLock<Option<Instant>>,
+
+    /// Time the server process was frozen at, if currently frozen.
+    frozen_at: RwLock<Option<Instant>>,
+
     /// List of banned IPs.
     banned_ips: RwLock<BannedIps>,
 
+    /// List of banned players.
+    banned_players: RwLock<BannedPlayers>,
+
     /// Whitelist if enabled.
     whitelist: RwLock<Option<Whitelist>>,
 
+    /// Index of next server address to use with round-robin address selection.
+    address_index: AtomicUsize,
+
+    /// Index of next starting status sample entry to show.
+    starting_sample_index: AtomicUsize,
+
+    /// Number of joining clients currently waiting for the server to start.
+    waiting: AtomicUsize,
+
+    /// Whether all clients waiting for the current server start disconnected.
+    abandoned_start: AtomicBool,
+
+    /// Number of consecutive server crashes.
+    crashes: AtomicU32,
+
+    /// Whether the server is being restarted after a crash.
+    crash_restarting: AtomicBool,
+
+    /// Whether the server was started outside of lazymc and is online.
+    external: AtomicBool,
+
+    /// Whether the last status response polled from the server was invalid.
+    status_invalid: AtomicBool,
+
+    /// Times of recent logins, used to limit number of logins per minute.
+    login_times: Mutex<VecDeque<Instant>>,
+
+    /// Last time each client IP woke the server, used for the per-IP wake cooldown.
+    ip_wakes: Mutex<HashMap<IpAddr, Instant>>,
+
     /// Lock for exclusive RCON operations.
     #[cfg(feature = "rcon")]
     rcon_lock: Semaphore,
@@ -94,6 +163,22 @@ pub struct Server {
     ///
     /// Sent to clients when they connect to lobby. Recorded from server by probe.
     pub forge_payload: RwLock<Vec<Vec<u8>>>,
+
+    /// Protocol health metrics.
+    pub metrics: Arc<Metrics>,
+
+    /// Reverse DNS lookup cache for client IPs.
+    reverse_dns: ReverseDns,
+
+    /// Activity of clients proxied to the server.
+    pub proxy_activity: Arc<Activity>,
+
+    /// How status responses built by lazymc are shrunk to fit the maximum size.
+    pub status_fit: StatusFitCache,
+
+    /// Queue of joining clients waiting for a free slot.
+    #[cfg(feature = "lobby")]
+    pub queue: Queue,
 }
 
 impl Server {
@@ -116,6 +201,12 @@ impl Server {
         self.update_state_from(None, state, config).await
     }
 
+    /// Set a new state, for tests.
+    #[cfg(test)]
+    pub async fn set_state(&self, state: State, config: &Config) {
+        self.update_state(state, config).await;
+    }
+
     /// Set new state, from a current state.
     ///
     /// This updates various other internal things depending on how the state changes.
@@ -157,18 +248,31 @@ impl Server {
             _ => None,
         };
 
-        // Online/offline messages
+        // Online/offline messages, remember time
         match new {
-            State::Started => info!(target: "lazymc::monitor", "Server is now online"),
-            State::Stopped => info!(target: "lazymc::monitor", "Server is now sleeping"),
+            State::Started => {
+                info!(target: "lazymc::monitor", "Server is now online");
+                self.started_at.write().await.replace(Instant::now());
+                self.crash_restarting.store(false, Ordering::Relaxed);
+            }
+            State::Stopped => {
+                info!(target: "lazymc::monitor", "Server is now sleeping");
+                self.stopped_at.write().await.replace(Instant::now());
+            }
             _ => {}
         }
 
         // If Starting -> Started, update active time and keep it online for configured time
+        // Don't keep it online if all clients waiting for it disconnected
+        let abandoned = self.abandoned_start.swap(false, Ordering::Relaxed);
         if old == State::Starting && new == State::Started {
             self.update_last_active().await;
-            self.keep_online_for(Some(config.time.min_online_time))
-                .await;
+            if abandoned {
+                debug!(target: "lazymc", "Server start was abandoned by waiting clients, not keeping it online");
+            } else {
+                self.keep_online_for(Some(config.time.min_online_time))
+                    .await;
+            }
         }
 
         true
@@ -179,22 +283,50 @@ impl Server {
     /// This updates various other internal things depending on the current state and the given
     /// status.
     pub async fn update_status(&self, config: &Config, status: Option<ServerStatus>) {
+        // Server is online while sleeping without process, it was started outside of lazymc
+        if self.state() == State::Stopped
+            && status.is_some()
+            && self.pid.lock().await.is_none()
+            && !self.external.swap(true, Ordering::Relaxed)
+        {
+            if config.server.adopt_external {
+                info!(target: "lazymc::monitor", "Server was started outside of lazymc, adopting it, it can only be put to sleep through RCON");
+                self.update_last_active().await;
+            } else {
+                warn!(target: "lazymc::monitor", "Server was started outside of lazymc, not adopting it because server.adopt_external is disabled");
+            }
+        }
+
         // Update state based on curren
         match (self.state(), &status) {
+            (State::Stopped, Some(_))
+                if !config.server.adopt_external && self.external.load(Ordering::Relaxed) => {}
             (State::Stopped | State::Starting, Some(_)) => {
                 self.update_state(State::Started, config).await;
             }
             (State::Started, None) => {
+                if self.external.load(Ordering::Relaxed) {
+                    info!(target: "lazymc::monitor", "Server started outside of lazymc was stopped");
+                }
+                self.update_state(State::Stopped, config).await;
+            }
+            // No process to wait for if started outside of lazymc
+            (State::Stopping, None) if self.external.load(Ordering::Relaxed) => {
                 self.update_state(State::Stopped, config).await;
             }
             _ => {}
         }
 
+        // Forget externally started server once it is offline
+        if status.is_none() {
+            self.external.store(false, Ordering::Relaxed);
+        }
+
         // Update last status if known
         if let Some(status) = status {
             // Update last active time if there are online players
             if status.players.online > 0 {
-                self.update_last_active().await;
+                self.record_activity(config, ActivitySource::Players).await;
             }
 
             self.status.write().await.replace(status);
@@ -205,6 +337,18 @@ impl Server {
     ///
     /// Does nothing if currently not in stopped state.
     pub async fn start(config: Arc<Config>, server: Arc<Server>, username: Option<String>) -> bool {
+        // Must not be in restart cooldown
+        if let Some(remaining) = server.restart_cooldown(&config).await {
+            debug!(target: "lazymc", "Not starting server, in restart cooldown for {}s", remaining.as_secs());
+            return false;
+        }
+
+        // Must not be running outside of lazymc already
+        if server.is_unadopted_external(&config) {
+            warn!(target: "lazymc", "Not starting server, it was started outside of lazymc and is not adopted");
+            return false;
+        }
+
         // Must set state from stopped to starting
         if !server
             .update_state_from(Some(State::Stopped), State::Starting, &config)
@@ -264,6 +408,71 @@ impl Server {
         false
     }
 
+    /// Stop server process, unfreezing it first if it is frozen.
+    ///
+    /// Used when lazymc quits, unlike `stop` this never freezes the server. Returns false if the
+    /// server process is unknown. Only available on Unix.
+    #[cfg(unix)]
+    pub async fn terminate(&self, config: &Config) -> bool {
+        // Grab PID
+        let pid = match *self.pid.lock().await {
+            Some(pid) => pid,
+            None => return false,
+        };
+
+        for from in [State::Starting, State::Started, State::Stopped] {
+            self.update_state_from(Some(from), State::Stopping, config)
+                .await;
+        }
+        self.frozen_at.write().await.take();
+
+        // Process must be unfrozen to handle stop signal
+        if !os::unfreeze(pid) || !os::kill_gracefully(pid) {
+            error!(target: "lazymc", "Failed to send stop signal to server process");
+            return false;
+        }
+
+        true
+    }
+
+    /// Wait until the server process exited.
+    ///
+    /// Returns immediately if no server process is running. Only available on Unix.
+    #[cfg(unix)]
+    pub async fn wait_for_exit(&self) {
+        while self.pid.lock().await.is_some() {
+            time::sleep(PROCESS_EXIT_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Stop server process that is frozen.
+    ///
+    /// Used as last sleep stage, to free server resources. Only available on Unix.
+    #[cfg(unix)]
+    pub async fn stop_frozen(&self, config: &Config) -> bool {
+        // Grab PID
+        let pid = match *self.pid.lock().await {
+            Some(pid) => pid,
+            None => return false,
+        };
+
+        if !self
+            .update_state_from(Some(State::Stopped), State::Stopping, config)
+            .await
+        {
+            return false;
+        }
+        self.frozen_at.write().await.take();
+
+        // Process must be unfrozen to handle stop signal
+        if !os::unfreeze(pid) || !os::kill_gracefully(pid) {
+            error!(target: "lazymc", "Failed to send stop signal to frozen server process");
+            return false;
+        }
+
+        true
+    }
+
     /// Force kill running server.
     ///
     /// This requires the server PID to be known.
@@ -284,14 +493,17 @@ impl Server {
         }
 
         // Never sleep if players are online
-        let players_online = self
+        let players = self
             .status
             .read()
             .await
             .as_ref()
-            .map(|status| status.players.online > 0)
-            .unwrap_or(false);
-        if players_online {
+            .map(|status| status.players.online)
+            .unwrap_or(0);
+
+        // Ignore online players if all are proxied and have been idle, such as zombie sessions
+        let proxy_idle_for = self.proxy_idle_for(config, players);
+        if players > 0 && proxy_idle_for.is_none() {
             trace!(target: "lazymc", "Not sleeping because players are online");
             return false;
         }
@@ -308,14 +520,57 @@ impl Server {
             return false;
         }
 
-        // Last active time must have passed sleep threshold
+        // Proxied clients or last active time must have passed sleep threshold
+        let sleep_after = Duration::from_secs(config.time.sleep_after as u64);
+        if let (true, Some(idle_for)) = (players > 0, proxy_idle_for) {
+            trace!(target: "lazymc", "Players online, but no proxied client activity for {}s", idle_for.as_secs());
+            return idle_for >= sleep_after;
+        }
         if let Some(last_idle) = self.last_active.read().await.as_ref() {
-            return last_idle.elapsed() >= Duration::from_secs(config.time.sleep_after as u64);
+            return last_idle.elapsed() >= sleep_after;
         }
 
         false
     }
 
+    /// Get time proxied clients sent no data for, if idle for the configured time.
+    ///
+    /// Returns `None` if not configured, if not idle long enough, or if not all online players
+    /// can be proxied clients because fewer connections are open, such as players connecting to
+    /// the server directly.
+    fn proxy_idle_for(&self, config: &Config, players: u32) -> Option<Duration> {
+        if config.server.proxy_byte_idle == 0 || self.proxy_activity.open_count() < players as usize
+        {
+            return None;
+        }
+
+        let idle_after = Duration::from_secs(config.server.proxy_byte_idle as u64);
+        self.proxy_activity
+            .idle_for()
+            .filter(|idle_for| *idle_for >= idle_after)
+    }
+
+    /// Check whether the server process is currently frozen.
+    pub async fn is_frozen(&self) -> bool {
+        self.frozen_at.read().await.is_some()
+    }
+
+    /// Decide whether the frozen server process should be stopped.
+    ///
+    /// Always returns false if not configured.
+    #[cfg(unix)]
+    pub async fn should_stop_frozen(&self, config: &Config) -> bool {
+        let freeze_for = Duration::from_secs(config.server.sleep_stages.freeze_for as u64);
+        config.server.sleep_stages.freeze_for > 0
+            && self.state() == State::Stopped
+            && self
+                .frozen_at
+                .read()
+                .await
+                .map(|t| t.elapsed() >= freeze_for)
+                .unwrap_or(false)
+    }
+
     /// Decide whether to force kill the server process.
     pub async fn should_kill(&self) -> bool {
         self.kill_at
@@ -325,6 +580,56 @@ impl Server {
             .unwrap_or(false)
     }
 
+    /// Whether the server was started outside of lazymc and is not adopted.
+    ///
+    /// Such server must not be started again, it is already running.
+    pub fn is_unadopted_external(&self, config: &Config) -> bool {
+        !config.server.adopt_external && self.external.load(Ordering::Relaxed)
+    }
+
+    /// Get remaining restart cooldown time.
+    ///
+    /// Returns `None` if the server is not in restart cooldown and may be started.
+    pub async fn restart_cooldown(&self, config: &Config) -> Option<Duration> {
+        let cooldown = Duration::from_secs(config.server.restart_cooldown as u64);
+        self.last_stop
+            .read()
+            .await
+            .and_then(|t| cooldown.checked_sub(t.elapsed()))
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Get remaining wake cooldown time for the given client IP.
+    ///
+    /// Returns `None` if the IP is not in wake cooldown and may wake the server. Loopback and
+    /// private IPs are never in cooldown.
+    pub async fn ip_wake_cooldown(&self, config: &Config, ip: &IpAddr) -> Option<Duration> {
+        if config.server.per_ip_wake_cooldown == 0 || is_trusted_ip(ip) {
+            return None;
+        }
+
+        let cooldown = Duration::from_secs(config.server.per_ip_wake_cooldown as u64);
+        self.ip_wakes
+            .lock()
+            .await
+            .get(ip)
+            .and_then(|t| cooldown.checked_sub(t.elapsed()))
+            .filter(|remaining| !remaining.is_zero())
+    }
+
+    /// Remember that the given client IP woke the server, for the per-IP wake cooldown.
+    pub async fn record_ip_wake(&self, config: &Config, ip: IpAddr) {
+        if config.server.per_ip_wake_cooldown == 0 || is_trusted_ip(&ip) {
+            return;
+        }
+
+        // Forget IPs no longer in cooldown
+        let cooldown = Duration::from_secs(config.server.per_ip_wake_cooldown as u64);
+        let mut ip_wakes = self.ip_wakes.lock().await;
+        ip_wakes.retain(|_, t| t.elapsed() < cooldown);
+        ip_wakes.insert(ip, Instant::now());
+    }
+
     /// Read last known server status.
     pub async fn status(&self) -> RwLockReadGuard<'_, Option<ServerStatus>> {
         self.status.read().await
@@ -335,6 +640,13 @@ impl Server {
         self.last_active.write().await.replace(Instant::now());
     }
 
+    /// Record activity from the given source, updates last active time if configured to count.
+    pub async fn record_activity(&self, config: &Config, source: ActivitySource) {
+        if config.server.activity_sources.contains(&source) {
+            self.update_last_active().await;
+        }
+    }
+
     /// Force the server to be online for the given number of seconds.
     async fn keep_online_for(&self, duration: Option<u32>) {
         *self.keep_online_until.write().await = duration
@@ -363,6 +675,17 @@ impl Server {
         futures::executor::block_on(async { self.is_banned_ip(ip).await })
     }
 
+    /// Get player ban entry, matching UUID if known or username.
+    ///
+    /// This uses the latest known `banned-players.json` contents if known.
+    pub async fn player_ban_entry(
+        &self,
+        uuid: Option<u128>,
+        username: &str,
+    ) -> Option<BannedPlayer> {
+        self.banned_players.read().await.get(uuid, username)
+    }
+
     /// Check whether the given username is whitelisted.
     ///
     /// Returns `true` if no whitelist is currently used.
@@ -385,6 +708,199 @@ impl Server {
         futures::executor::block_on(async { self.set_banned_ips(ips).await })
     }
 
+    /// Update the list of banned players.
+    pub async fn set_banned_players(&self, players: BannedPlayers) {
+        *self.banned_players.write().await = players;
+    }
+
+    /// Update the list of banned players.
+    pub fn set_banned_players_blocking(&self, players: BannedPlayers) {
+        futures::executor::block_on(async { self.set_banned_players(players).await })
+    }
+
+    /// Format client IP for logging, with its hostname if reverse DNS is enabled and known.
+    ///
+    /// Starts a reverse DNS lookup in the background if not known yet.
+    pub fn display_ip(&self, config: &Config, ip: IpAddr) -> String {
+        if !config.advanced.reverse_dns {
+            return ip.to_string();
+        }
+
+        match self.reverse_dns.hostname(ip) {
+            Some(hostname) => format!("{ip} ({hostname})"),
+            None => ip.to_string(),
+        }
+    }
+
+    /// Check whether the server is online without players.
+    pub async fn is_empty(&self) -> bool {
+        self.state() == State::Started
+            && self
+                .status()
+                .await
+                .as_ref()
+                .map(|status| status.players.online == 0)
+                .unwrap_or(false)
+    }
+
+    /// Check whether the server is online and full, as reported by its status.
+    #[cfg(feature = "lobby")]
+    pub async fn is_full(&self) -> bool {
+        self.state() == State::Started
+            && self
+                .status()
+                .await
+                .as_ref()
+                .map(|status| status.players.online >= status.players.max)
+                .unwrap_or(false)
+    }
+
+    /// Check whether the server is online without players.
+    pub fn is_empty_blocking(&self) -> bool {
+        futures::executor::block_on(async { self.is_empty().await })
+    }
+
+    /// Get server addresses to proxy a client to, in order of preference.
+    ///
+    /// Based on the configured address selection policy, rotates to the next address with
+    /// round-robin selection. Always returns at least one address.
+    pub fn proxy_addresses(&self, config: &Config) -> Vec<SocketAddr> {
+        let index = match config.server.address_selection {
+            AddressSelection::RoundRobin => self.address_index.fetch_add(1, Ordering::Relaxed),
+            AddressSelection::First | AddressSelection::Failover => 0,
+        };
+        select_addresses(
+            &config.server.addresses,
+            config.server.address_selection,
+            index,
+        )
+    }
+
+    /// Get server addresses to poll or probe the server through, in order of preference.
+    ///
+    /// Never rotates, all addresses are tried in failover order unless only the first address
+    /// should be used. Always returns at least one address.
+    pub fn poll_addresses(&self, config: &Config) -> Vec<SocketAddr> {
+        let selection = match config.server.address_selection {
+            AddressSelection::First => AddressSelection::First,
+            AddressSelection::RoundRobin | AddressSelection::Failover => AddressSelection::Failover,
+        };
+        select_addresses(&config.server.addresses, selection, 0)
+    }
+
+    /// Get next status sample entry to show while starting.
+    ///
+    /// Rotates through the configured entries on each call. Returns `None` if none are configured.
+    pub fn next_starting_sample<'a>(&self, config: &'a Config) -> Option<&'a str> {
+        let sample = &config.motd.starting_sample;
+        if sample.is_empty() {
+            return None;
+        }
+
+        let index = self.starting_sample_index.fetch_add(1, Ordering::Relaxed);
+        Some(&sample[index % sample.len()])
+    }
+
+    /// Whether the server is being restarted after it crashed, until it is online again.
+    pub fn is_crash_restarting(&self) -> bool {
+        self.crash_restarting.load(Ordering::Relaxed)
+    }
+
+    /// Set whether the last status response polled from the server was invalid.
+    ///
+    /// Returns whether it was invalid before.
+    pub fn set_status_invalid(&self, invalid: bool) -> bool {
+        self.status_invalid.swap(invalid, Ordering::Relaxed)
+    }
+
+    /// Whether the last status response polled from the server was invalid.
+    pub fn is_status_invalid(&self) -> bool {
+        self.status_invalid.load(Ordering::Relaxed)
+    }
+
+    /// Get time the server has been online for.
+    ///
+    /// Returns `None` if not online or unknown.
+    pub async fn uptime(&self) -> Option<Duration> {
+        match self.state() {
+            State::Started | State::Stopping => self.started_at.read().await.map(|at| at.elapsed()),
+            State::Stopped | State::Starting => None,
+        }
+    }
+
+    /// Get time the server has been sleeping for.
+    ///
+    /// Returns `None` if not sleeping or unknown, such as when it has not run since lazymc started.
+    pub async fn asleep_for(&self) -> Option<Duration> {
+        match self.state() {
+            State::Stopped | State::Starting => self.stopped_at.read().await.map(|at| at.elapsed()),
+            State::Started | State::Stopping => None,
+        }
+    }
+
+    /// Register a joining client as waiting for the server to start.
+    ///
+    /// Returns `None` if the configured maximum number of waiting clients is reached. The client
+    /// is unregistered when the returned guard is dropped.
+    pub fn try_wait(&self, config: &Config) -> Option<WaitingGuard<'_>> {
+        let waiting = self.waiting.fetch_add(1, Ordering::Relaxed);
+        let max = config.join.max_waiting as usize;
+        if max > 0 && waiting >= max {
+            self.waiting.fetch_sub(1, Ordering::Relaxed);
+            return None;
+        }
+
+        // Server start isn't abandoned while a client is waiting for it
+        self.abandoned_start.store(false, Ordering::Relaxed);
+
+        Some(WaitingGuard(&self.waiting))
+    }
+
+    /// Mark current server start as abandoned, when a waiting client disconnected.
+    ///
+    /// Does nothing if other clients are still waiting for the server to start. Stops the server
+    /// if configured.
+    pub async fn abandon_start(&self, config: &Config) {
+        // Must be starting, the disconnected client itself is still registered as waiting
+        if self.state() != State::Starting || self.waiting.load(Ordering::Relaxed) > 1 {
+            return;
+        }
+
+        if config.join.cancel_abandoned_start {
+            info!(target: "lazymc", "All clients waiting for server disconnected, cancelling start...");
+            self.stop(config).await;
+        } else {
+            self.abandoned_start.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Register a login, unless the configured maximum number of logins per minute is reached.
+    ///
+    /// Returns `false` if the login must be throttled.
+    pub async fn try_login(&self, config: &Config) -> bool {
+        let max = config.join.max_logins_per_minute as usize;
+        if max == 0 {
+            return true;
+        }
+
+        // Forget logins older than a minute
+        let mut login_times = self.login_times.lock().await;
+        while login_times
+            .front()
+            .map(|at| at.elapsed() >= LOGIN_THROTTLE_WINDOW)
+            .unwrap_or(false)
+        {
+            login_times.pop_front();
+        }
+
+        if login_times.len() >= max {
+            return false;
+        }
+
+        login_times.push_back(Instant::now());
+        true
+    }
+
     /// Update the whitelist.
     pub async fn set_whitelist(&self, whitelist: Option<Whitelist>) {
         *self.whitelist.write().await = whitelist;
@@ -409,18 +925,50 @@ impl Default for Server {
             last_active: Default::default(),
             keep_online_until: Default::default(),
             kill_at: Default::default(),
+            last_stop: Default::default(),
+            started_at: Default::default(),
+            stopped_at: Default::default(),
+            frozen_at: Default::default(),
             banned_ips: Default::default(),
+            banned_players: Default::default(),
             whitelist: Default::default(),
+            address_index: Default::default(),
+            starting_sample_index: Default::default(),
+            waiting: Default::default(),
+            abandoned_start: Default::default(),
+            crashes: Default::default(),
+            crash_restarting: Default::default(),
+            external: Default::default(),
+            status_invalid: Default::default(),
+            login_times: Default::default(),
+            ip_wakes: Default::default(),
             #[cfg(feature = "rcon")]
             rcon_lock: Semaphore::new(1),
             #[cfg(feature = "rcon")]
             rcon_last_stop: Default::default(),
             probed_join_game: Default::default(),
             forge_payload: Default::default(),
+            metrics: Default::default(),
+            reverse_dns: Default::default(),
+            proxy_activity: Default::default(),
+            status_fit: Default::default(),
+            #[cfg(feature = "lobby")]
+            queue: Default::default(),
         }
     }
 }
 
+/// Guard for a client waiting for the server to start.
+///
+/// See `Server::try_wait`.
+pub struct WaitingGuard<'a>(&'a AtomicUsize);
+
+impl Drop for WaitingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
 /// Server state.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 pub enum State {
@@ -460,6 +1008,49 @@ impl State {
     }
 }
 
+/// Forward server process output to log line by line, until the stream closes.
+///
+/// Runs in its own task so it never blocks proxying. If logging can't keep up the pipe fills up,
+/// which only slows down server output.
+async fn forward_output(output: impl AsyncRead + Unpin, level: log::Level) {
+    let mut reader = BufReader::new(output);
+    let mut line = vec![];
+    loop {
+        line.clear();
+        match reader.read_until(b'\n', &mut line).await {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {}
+        }
+        let line = String::from_utf8_lossy(&line);
+        log!(target: "lazymc::server", level, "{}", line.trim_end());
+    }
+}
+
+/// Select server addresses to connect to, in order of preference.
+///
+/// The index selects the address to use with round-robin selection.
+fn select_addresses(
+    addrs: &[SocketAddr],
+    selection: AddressSelection,
+    index: usize,
+) -> Vec<SocketAddr> {
+    match selection {
+        AddressSelection::First => addrs.iter().take(1).copied().collect(),
+        AddressSelection::RoundRobin if !addrs.is_empty() => vec![addrs[index % addrs.len()]],
+        AddressSelection::RoundRobin => vec![],
+        AddressSelection::Failover => addrs.to_vec(),
+    }
+}
+
+/// Check whether IP is trusted, being a loopback or private network address.
+fn is_trusted_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback() || ip.is_private() || ip.is_link_local(),
+        // Loopback, or unique local address (fc00::/7)
+        IpAddr::V6(ip) => ip.is_loopback() || (ip.segments()[0] & 0xfe00) == 0xfc00,
+    }
+}
+
 /// Invoke server command, store PID and wait for it to quit.
 pub async fn invoke_server_cmd(
     config: Arc<Config>,
@@ -476,6 +1067,62 @@ pub async fn invoke_server_cmd(
         cmd.current_dir(dir);
     }
 
+    // Set process niceness before exec, so all server threads inherit it
+    #[cfg(unix)]
+    if let Some(nice) = config.server.nice {
+        // Safety: only calls setpriority in forked child, errors are checked after spawning
+        unsafe {
+            cmd.pre_exec(move || {
+                os::set_nice_self(nice);
+                Ok(())
+            });
+        }
+    }
+
+    // Run in own process group so Ctrl-C in terminal doesn't reach server, lazymc handles it
+    // A background process group reading the terminal is stopped by SIGTTIN, so detach stdin
+    #[cfg(unix)]
+    if !config.advanced.sigint_stops_backend {
+        cmd.stdin(Stdio::null());
+        // Safety: only calls setpgid in forked child
+        unsafe {
+            cmd.pre_exec(|| {
+                libc::setpgid(0, 0);
+                Ok(())
+            });
+        }
+    }
+
+    // Move into cgroup before exec, so all server threads start in it
+    #[cfg(target_os = "linux")]
+    if let Some(procs) = config.server.cgroup.as_deref().and_then(os::cgroup_procs) {
+        // Safety: only calls async-signal-safe functions in forked child, checked after spawning
+        unsafe {
+            cmd.pre_exec(move || {
+                os::set_cgroup_self(&procs);
+                Ok(())
+            });
+        }
+    }
+
+    // Don't inherit CPU affinity lazymc is pinned to, restore original
+    #[cfg(target_os = "linux")]
+    if let Some(affinity) = os::original_cpu_affinity() {
+        // Safety: only calls sched_setaffinity in forked child
+        unsafe {
+            cmd.pre_exec(move || {
+                affinity.apply();
+                Ok(())
+            });
+        }
+    }
+
+    // Capture output to forward it
+    if config.server.forward_output {
+        cmd.stdout(Stdio::piped());
+        cmd.stderr(Stdio::piped());
+    }
+
     // Spawn process
     let mut child = match cmd.spawn() {
         Ok(child) => child,
@@ -485,12 +1132,21 @@ pub async fn invoke_server_cmd(
         }
     };
 
+    // Forward server output to log
+    let level = config.server.forward_output_level.level();
+    if let Some(stdout) = child.stdout.take() {
+        tokio::spawn(forward_output(stdout, level));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        tokio::spawn(forward_output(stderr, level));
+    }
+
     // Remember PID
-    state
-        .pid
-        .lock()
-        .await
-        .replace(child.id().expect("unknown server PID"));
+    let pid = child.id().expect("unknown server PID");
+    state.pid.lock().await.replace(pid);
+
+    // Verify process niceness and cgroup
+    apply_process_resources(&config, pid);
 
     // Wait for process to exit, handle status
     let crashed = match child.wait().await {
@@ -508,7 +1164,12 @@ pub async fn invoke_server_cmd(
             false
         }
         Ok(status) => {
-            warn!(target: "lazymc", "Server process stopped with error code ({})", status);
+            match status.code() {
+                Some(code) => {
+                    warn!(target: "lazymc", "Server process stopped with error code {}", code)
+                }
+                None => warn!(target: "lazymc", "Server process stopped with error ({})", status),
+            }
             state.state() == State::Started
         }
         Err(err) => {
@@ -518,8 +1179,35 @@ pub async fn invoke_server_cmd(
         }
     };
 
-    // Forget server PID
+    // Count consecutive crashes, reset if server was online for a while or stopped normally
+    if crashed {
+        match state.uptime().await {
+            Some(uptime) if uptime >= CRASH_RESET_UPTIME => {
+                state.crashes.store(1, Ordering::Relaxed)
+            }
+            _ => {
+                state.crashes.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    } else {
+        state.crashes.store(0, Ordering::Relaxed);
+    }
+
+    // Restart on crash, give up after too many consecutive crashes
+    let crashes = state.crashes.load(Ordering::Relaxed);
+    let limit = config.server.crash_restart_limit;
+    let restart = crashed && config.server.wake_on_crash && (limit == 0 || crashes <= limit);
+    if crashed && config.server.wake_on_crash && !restart {
+        error!(target: "lazymc", "Server crashed {} times in a row, not restarting it", crashes);
+    }
+
+    // Kick joining clients with crash message until restarted
+    state.crash_restarting.store(restart, Ordering::Relaxed);
+
+    // Forget server PID, remember stop time for restart cooldown
     state.pid.lock().await.take();
+    state.frozen_at.write().await.take();
+    state.last_stop.write().await.replace(Instant::now());
 
     // Give server a little more time to quit forgotten threads
     time::sleep(SERVER_QUIT_COOLDOWN).await;
@@ -527,15 +1215,65 @@ pub async fn invoke_server_cmd(
     // Set server state to stopped
     state.update_state(State::Stopped, &config).await;
 
-    // Restart on crash
-    if crashed && config.server.wake_on_crash {
-        warn!(target: "lazymc", "Server crashed, restarting...");
+    // Restart on crash, with backoff
+    if restart {
+        let backoff = crash_restart_backoff(config.server.crash_restart_backoff, crashes);
+        if backoff.is_zero() {
+            warn!(target: "lazymc", "Server crashed, restarting...");
+        } else {
+            warn!(target: "lazymc", "Server crashed, restarting in {}s...", backoff.as_secs());
+            time::sleep(backoff).await;
+        }
+
+        // Wait for restart cooldown
+        if let Some(remaining) = state.restart_cooldown(&config).await {
+            time::sleep(remaining).await;
+        }
+
         Server::start(config, state, None).await;
     }
 
     Ok(())
 }
 
+/// Get delay before restarting a crashed server, for the given number of consecutive crashes.
+///
+/// The base delay in seconds is doubled for each consecutive crash. A base of 0 restarts
+/// immediately.
+fn crash_restart_backoff(base: u32, crashes: u32) -> Duration {
+    Duration::from_secs(base as u64)
+        .saturating_mul(2u32.saturating_pow(crashes.saturating_sub(1)))
+        .min(CRASH_RESTART_BACKOFF_MAX)
+}
+
+/// Check configured niceness and cgroup applied, move server process into cgroup if not.
+///
+/// Logs a warning and continues if this is not supported on this platform.
+#[allow(unused_variables)]
+fn apply_process_resources(config: &Config, pid: u32) {
+    // Niceness is set before exec, verify it applied
+    if let Some(nice) = config.server.nice {
+        #[cfg(unix)]
+        if os::nice(pid) != nice {
+            warn!(target: "lazymc", "Failed to set server process niceness to {}, continuing", nice);
+        }
+
+        #[cfg(not(unix))]
+        warn!(target: "lazymc", "Setting server process niceness is not supported on this platform, ignoring");
+    }
+
+    // Cgroup is set before exec, verify it applied or move it now
+    if let Some(ref cgroup) = config.server.cgroup {
+        #[cfg(target_os = "linux")]
+        if !os::in_cgroup(pid, cgroup) {
+            os::set_cgroup(pid, cgroup);
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        warn!(target: "lazymc", "Setting server process cgroup is only supported on Linux, ignoring");
+    }
+}
+
 /// Stop server through RCON.
 #[cfg(feature = "rcon")]
 async fn stop_server_rcon(config: &Config, server: &Server) -> bool {
@@ -632,7 +1370,9 @@ async fn freeze_server_signal(config: &Config, server: &Server) -> bool {
         }
     };
 
-    if !os::freeze(pid) {
+    if os::freeze(pid) {
+        server.frozen_at.write().await.replace(Instant::now());
+    } else {
         error!(target: "lazymc", "Failed to send freeze signal to server process.");
     }
 
@@ -663,6 +1403,7 @@ async fn unfreeze_server_signal(config: &Config, server: &Server) -> bool {
     if !os::unfreeze(pid) {
         error!(target: "lazymc", "Failed to send unfreeze signal to server process.");
     }
+    server.frozen_at.write().await.take();
 
     server
         .update_state_from(Some(State::Stopping), State::Starting, config)
@@ -673,3 +1414,299 @@ async fn unfreeze_server_signal(config: &Config, server: &Server) -> bool {
 
     true
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use minecraft_protocol::data::chat::{Message, Payload};
+    use minecraft_protocol::data::server_status::{OnlinePlayers, ServerVersion};
+
+    fn addrs() -> Vec<SocketAddr> {
+        vec![
+            "10.0.0.1:25566".parse().unwrap(),
+            "10.0.0.2:25566".parse().unwrap(),
+            "10.0.0.3:25566".parse().unwrap(),
+        ]
+    }
+
+    #[test]
+    fn select_addresses_first() {
+        let addrs = addrs();
+        for index in 0..4 {
+            assert_eq!(
+                select_addresses(&addrs, AddressSelection::First, index),
+                vec![addrs[0]]
+            );
+        }
+    }
+
+    #[test]
+    fn select_addresses_round_robin() {
+        let addrs = addrs();
+        let selected = (0..4)
+            .map(|index| select_addresses(&addrs, AddressSelection::RoundRobin, index))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            selected,
+            vec![
+                vec![addrs[0]],
+                vec![addrs[1]],
+                vec![addrs[2]],
+                vec![addrs[0]]
+            ]
+        );
+    }
+
+    #[test]
+    fn select_addresses_failover() {
+        let addrs = addrs();
+        assert_eq!(
+            select_addresses(&addrs, AddressSelection::Failover, 1),
+            addrs
+        );
+    }
+
+    #[test]
+    fn poll_addresses_never_rotate() {
+        let server = Server::default();
+        let mut config: Config = toml::from_str("[server]\ncommand = \"true\"").unwrap();
+        config.server.addresses = addrs();
+        config.server.address_selection = AddressSelection::RoundRobin;
+
+        for _ in 0..3 {
+            assert_eq!(server.poll_addresses(&config), addrs());
+        }
+        assert_eq!(server.proxy_addresses(&config), vec![addrs()[0]]);
+        assert_eq!(server.proxy_addresses(&config), vec![addrs()[1]]);
+    }
+
+    /// Build started server with the given number of players online.
+    fn online_status(players: u32) -> ServerStatus {
+        ServerStatus {
+            version: ServerVersion {
+                name: "1.20.4".into(),
+                protocol: 765,
+            },
+            description: Message::new(Payload::text("A Minecraft Server")),
+            players: OnlinePlayers {
+                online: players,
+                max: 20,
+                sample: vec![],
+            },
+            favicon: None,
+        }
+    }
+
+    async fn started_server(config: &Config, players: u32) -> Server {
+        let server = Server::default();
+        server
+            .update_status(config, Some(online_status(players)))
+            .await;
+        assert_eq!(server.state(), State::Started);
+        server
+    }
+
+    #[tokio::test]
+    async fn should_sleep_proxy_byte_idle() {
+        let ago = |secs| Instant::now() - Duration::from_secs(secs);
+        let mut config: Config = toml::from_str("[server]\ncommand = \"true\"").unwrap();
+        config.time.sleep_after = 90;
+
+        // Players online, not configured
+        let server = started_server(&config, 1).await;
+        let silent = server.proxy_activity.open();
+        silent.touch_at(ago(120));
+        assert!(!server.should_sleep(&config).await);
+        drop(silent);
+
+        config.server.proxy_byte_idle = 30;
+
+        // No players online, server was just active
+        let server = started_server(&config, 0).await;
+        let silent = server.proxy_activity.open();
+        silent.touch_at(ago(120));
+        assert!(!server.should_sleep(&config).await);
+        drop(silent);
+
+        // Players online, not connected through lazymc
+        let server = started_server(&config, 1).await;
+        assert!(!server.should_sleep(&config).await);
+
+        // Players online, connection just opened
+        let server = started_server(&config, 1).await;
+        let _open = server.proxy_activity.open();
+        assert!(!server.should_sleep(&config).await);
+
+        // Players online, connection had data recently
+        let server = started_server(&config, 1).await;
+        let open = server.proxy_activity.open();
+        open.touch_at(ago(20));
+        assert!(!server.should_sleep(&config).await);
+        drop(open);
+
+        // Players online, connection silent, but not for sleep time
+        let server = started_server(&config, 1).await;
+        let silent = server.proxy_activity.open();
+        silent.touch_at(ago(60));
+        assert!(!server.should_sleep(&config).await);
+        drop(silent);
+
+        // Players online, connection silent for sleep time
+        let server = started_server(&config, 1).await;
+        let silent = server.proxy_activity.open();
+        silent.touch_at(ago(120));
+        assert!(server.should_sleep(&config).await);
+
+        // Players online, one connection silent and one active
+        let active = server.proxy_activity.open();
+        assert!(!server.should_sleep(&config).await);
+        drop((silent, active));
+
+        // More players online than proxied connections, one silent
+        let server = started_server(&config, 2).await;
+        let silent = server.proxy_activity.open();
+        silent.touch_at(ago(120));
+        assert!(!server.should_sleep(&config).await);
+        drop(silent);
+
+        // Players online, last connection closed
+        let server = started_server(&config, 1).await;
+        let silent = server.proxy_activity.open();
+        silent.touch_at(ago(120));
+        drop(silent);
+        assert!(!server.should_sleep(&config).await);
+
+        // Players online, keep online time not expired
+        let server = started_server(&config, 1).await;
+        server.keep_online_for(Some(60)).await;
+        let silent = server.proxy_activity.open();
+        silent.touch_at(ago(120));
+        assert!(!server.should_sleep(&config).await);
+    }
+
+    #[tokio::test]
+    async fn should_sleep_activity_sources() {
+        let all = [
+            ActivitySource::Players,
+            ActivitySource::Login,
+            ActivitySource::Status,
+        ];
+
+        // Test every source against every combination of configured sources
+        for mask in 0..(1 << all.len()) {
+            let mut config: Config = toml::from_str("[server]\ncommand = \"true\"").unwrap();
+            config.server.activity_sources = all
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| mask & (1 << i) != 0)
+                .map(|(_, source)| *source)
+                .collect();
+
+            for source in all {
+                // Server was last active before sleep time
+                let server = started_server(&config, 0).await;
+                let sleep_after = Duration::from_secs(config.time.sleep_after as u64);
+                server
+                    .last_active
+                    .write()
+                    .await
+                    .replace(Instant::now() - sleep_after);
+                assert!(server.should_sleep(&config).await);
+
+                match source {
+                    ActivitySource::Players => {
+                        server.update_status(&config, Some(online_status(1))).await;
+                        server.update_status(&config, Some(online_status(0))).await;
+                    }
+                    source => server.record_activity(&config, source).await,
+                }
+
+                let counts = config.server.activity_sources.contains(&source);
+                assert_eq!(
+                    server.should_sleep(&config).await,
+                    !counts,
+                    "{:?} with sources {:?}",
+                    source,
+                    config.server.activity_sources,
+                );
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn try_login_throttles() {
+        let server = Server::default();
+        let mut config: Config = toml::from_str("[server]\ncommand = \"true\"").unwrap();
+        for _ in 0..3 {
+            assert!(server.try_login(&config).await);
+        }
+
+        config.join.max_logins_per_minute = 2;
+        assert!(server.try_login(&config).await);
+        assert!(server.try_login(&config).await);
+        assert!(!server.try_login(&config).await);
+    }
+
+    #[test]
+    fn crash_restart_backoff_doubles() {
+        let base = Duration::from_secs(5);
+        assert_eq!(crash_restart_backoff(5, 0), base);
+        assert_eq!(crash_restart_backoff(5, 1), base);
+        assert_eq!(crash_restart_backoff(5, 2), base * 2);
+        assert_eq!(crash_restart_backoff(5, 3), base * 4);
+        assert_eq!(crash_restart_backoff(5, 20), CRASH_RESTART_BACKOFF_MAX);
+        assert_eq!(
+            crash_restart_backoff(5, u32::MAX),
+            CRASH_RESTART_BACKOFF_MAX
+        );
+    }
+
+    #[test]
+    fn crash_restart_backoff_default_immediate() {
+        let config: Config = toml::from_str("[server]\ncommand = \"true\"").unwrap();
+        assert_eq!(config.server.crash_restart_limit, 0);
+        assert_eq!(config.server.crash_restart_backoff, 0);
+        assert!(crash_restart_backoff(0, 1).is_zero());
+        assert!(crash_restart_backoff(0, u32::MAX).is_zero());
+    }
+
+    #[tokio::test]
+    async fn crashed_server_restarts() {
+        let config: Config = toml::from_str(
+            "[server]\ncommand = \"sh -c 'sleep 1; exit 1'\"\nwake_on_crash = true\ncrash_restart_backoff = 5",
+        )
+        .unwrap();
+        let config = Arc::new(config);
+        let server = Arc::new(Server::default());
+        let task = tokio::spawn(invoke_server_cmd(config.clone(), server.clone()).map(|_| ()));
+
+        // Server process comes online
+        time::timeout(Duration::from_secs(5), async {
+            while server.pid.lock().await.is_none() {
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        server.update_state(State::Starting, &config).await;
+        server.update_status(&config, Some(online_status(1))).await;
+        assert_eq!(server.state(), State::Started);
+
+        // Process exits unexpectedly, server is restarted after backoff
+        time::timeout(Duration::from_secs(10), async {
+            while server.state() != State::Stopped {
+                time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(server.crashes.load(Ordering::Relaxed), 1);
+        assert!(server.is_crash_restarting());
+        assert!(!task.is_finished());
+        task.abort();
+
+        // Crash message is shown until online again
+        server.update_status(&config, Some(online_status(0))).await;
+        assert!(!server.is_crash_restarting());
+    }
+}
@@ -6,7 +6,7 @@ use std::time::Duration;
 use notify::{watcher, DebouncedEvent, RecursiveMode, Watcher};
 
 use crate::config::{Config, Server as ConfigServer};
-use crate::mc::ban::{self, BannedIps};
+use crate::mc::ban::{self, BannedIps, BannedPlayers};
 use crate::mc::{server_properties, whitelist};
 use crate::server::Server;
 
@@ -19,7 +19,7 @@ pub fn service(config: Arc<Config>, server: Arc<Server>) {
     let dir = match ConfigServer::server_directory(&config) {
         Some(dir) if dir.is_dir() => dir,
         _ => {
-            warn!(target: "lazymc", "Server directory doesn't exist, can't watch file changes to reload whitelist and banned IPs");
+            warn!(target: "lazymc", "Server directory doesn't exist, can't watch file changes to reload whitelist and bans");
             return;
         }
     };
@@ -29,6 +29,7 @@ pub fn service(config: Arc<Config>, server: Arc<Server>) {
     while {
         // Update all files once
         reload_bans(&config, &server, &dir.join(ban::FILE));
+        reload_player_bans(&config, &server, &dir.join(ban::PLAYERS_FILE));
         reload_whitelist(&config, &server, &dir);
 
         // Watch for changes, update accordingly
@@ -102,6 +103,11 @@ fn update(config: &Config, server: &Server, dir: &Path, path: &Path) {
         reload_bans(config, server, path);
     }
 
+    // Update player bans
+    if path.ends_with(ban::PLAYERS_FILE) {
+        reload_player_bans(config, server, path);
+    }
+
     // Update whitelist
     if path.ends_with(whitelist::WHITELIST_FILE)
         || path.ends_with(whitelist::OPS_FILE)
@@ -143,6 +149,31 @@ fn reload_bans(config: &Config, server: &Server, path: &Path) {
     }
 }
 
+/// Reload banned players.
+fn reload_player_bans(config: &Config, server: &Server, path: &Path) {
+    // Player bans must be enabled
+    if !config.bans.by_uuid {
+        return;
+    }
+
+    trace!(target: "lazymc", "Reloading banned players...");
+
+    // File must exist, clear file otherwise
+    if !path.is_file() {
+        debug!(target: "lazymc", "No banned players, {} does not exist", ban::PLAYERS_FILE);
+        server.set_banned_players_blocking(BannedPlayers::default());
+        return;
+    }
+
+    // Load and update banned players
+    match ban::load_players(path) {
+        Ok(players) => server.set_banned_players_blocking(players),
+        Err(err) => {
+            debug!(target: "lazymc", "Failed load banned players from {}, ignoring: {}", ban::PLAYERS_FILE, err);
+        }
+    }
+}
+
 /// Reload whitelisted users.
 fn reload_whitelist(config: &Config, server: &Server, dir: &Path) {
     // Whitelist must be enabled
@@ -0,0 +1,175 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time;
+
+use crate::config::Config;
+use crate::metrics::MetricsSnapshot;
+use crate::server::Server;
+
+/// Maximum size of a request to the metrics endpoint.
+const MAX_REQUEST_SIZE: usize = 8 * 1024;
+
+/// Time to wait for a request to the metrics endpoint.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Metrics logging task.
+///
+/// Periodically logs a summary of protocol health and handoff metrics, if enabled.
+pub async fn service(config: Arc<Config>, server: Arc<Server>) {
+    // Interval logging must be enabled
+    if config.advanced.metrics_log_interval == 0 {
+        return;
+    }
+
+    let mut interval = time::interval(Duration::from_secs(
+        config.advanced.metrics_log_interval as u64,
+    ));
+    interval.tick().await;
+
+    // Metrics are cumulative, report difference with last interval
+    let mut last = server.metrics.snapshot();
+
+    loop {
+        interval.tick().await;
+
+        // Only report if anything happened
+        let current = server.metrics.snapshot();
+        let metrics = current.since(&last);
+        last = current;
+        if metrics.has_protocol_errors() {
+            info!(
+                target: "lazymc::metrics",
+                "Protocol errors in last {}s: handshake decode failures: {}, login start decode failures: {}, unhandled packets: {}",
+                config.advanced.metrics_log_interval,
+                metrics.handshake_errors,
+                metrics.login_start_errors,
+                metrics.unhandled_packets,
+            );
+        }
+        if metrics.has_handoffs() {
+            info!(
+                target: "lazymc::metrics",
+                "Handoffs to server in last {}s: started: {}, cold started: {}",
+                config.advanced.metrics_log_interval,
+                metrics.handoff_started,
+                metrics.handoff_cold_started,
+            );
+        }
+    }
+}
+
+/// Metrics endpoint task.
+///
+/// Serves metrics over HTTP in Prometheus text format at `/metrics`, if enabled.
+pub async fn endpoint(config: Arc<Config>, server: Arc<Server>) {
+    // Endpoint must be enabled
+    let addr = match config.advanced.metrics_address {
+        Some(addr) => addr,
+        None => return,
+    };
+
+    let listener = match TcpListener::bind(addr).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            error!(target: "lazymc::metrics", "Failed to start metrics endpoint on {}: {}", addr, err);
+            return;
+        }
+    };
+    info!(target: "lazymc::metrics", "Serving metrics on http://{}/metrics", addr);
+
+    while let Ok((stream, _)) = listener.accept().await {
+        let server = server.clone();
+        tokio::spawn(async move {
+            if serve_request(stream, &server).await.is_err() {
+                debug!(target: "lazymc::metrics", "Failed to serve metrics request");
+            }
+        });
+    }
+}
+
+/// Serve a single metrics request, then close the connection.
+async fn serve_request(mut stream: TcpStream, server: &Server) -> Result<(), ()> {
+    // Read request head
+    let mut request = Vec::new();
+    let read = async {
+        let mut buf = [0; 1024];
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") {
+            let read = stream.read(&mut buf).await.map_err(|_| ())?;
+            if read == 0 || request.len() + read > MAX_REQUEST_SIZE {
+                return Err(());
+            }
+            request.extend_from_slice(&buf[..read]);
+        }
+        Ok(())
+    };
+    time::timeout(REQUEST_TIMEOUT, read)
+        .await
+        .map_err(|_| ())??;
+
+    let response = response(&request, &server.metrics.snapshot());
+    stream.write_all(&response).await.map_err(|_| ())?;
+    stream.shutdown().await.map_err(|_| ())
+}
+
+/// Build HTTP response for the given request.
+fn response(request: &[u8], metrics: &MetricsSnapshot) -> Vec<u8> {
+    let request = String::from_utf8_lossy(request);
+    let mut request_line = request.lines().next().unwrap_or("").split(' ');
+    let (status, content_type, body) = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/metrics")) => (
+            "200 OK",
+            "text/plain; version=0.0.4; charset=utf-8",
+            metrics.prometheus(),
+        ),
+        _ => (
+            "404 Not Found",
+            "text/plain; charset=utf-8",
+            "Not found\n".into(),
+        ),
+    };
+
+    format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+    .into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::Metrics;
+
+    #[test]
+    fn metrics_response() {
+        let metrics = Metrics::default();
+        metrics.handshake_errors.incr(Some(765));
+
+        let response = response(
+            b"GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            &metrics.snapshot(),
+        );
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("lazymc_handshake_errors_total{protocol=\"765\"} 1\n"));
+
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.contains(&format!("Content-Length: {}", body.len())));
+    }
+
+    #[test]
+    fn not_found_response() {
+        let snapshot = Metrics::default().snapshot();
+        for request in [
+            &b"GET / HTTP/1.1\r\n\r\n"[..],
+            b"POST /metrics HTTP/1.1\r\n\r\n",
+            b"\r\n\r\n",
+        ] {
+            let response = String::from_utf8(response(request, &snapshot)).unwrap();
+            assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        }
+    }
+}
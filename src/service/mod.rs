@@ -1,5 +1,7 @@
 pub mod file_watcher;
+pub mod metrics;
 pub mod monitor;
 pub mod probe;
 pub mod server;
 pub mod signal;
+pub mod warm_ping;
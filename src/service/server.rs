@@ -1,25 +1,66 @@
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use bytes::BytesMut;
 use futures::FutureExt;
 use tokio::net::{TcpListener, TcpStream};
+use tokio::runtime;
 
-use crate::config::Config;
-use crate::proto::client::Client;
-use crate::proxy::{self, ProxyHeader};
+use crate::config::{ActivitySource, Config};
+#[cfg(target_os = "linux")]
+use crate::os;
+use crate::proto::client::{Client, ClientState};
+use crate::proto::peek;
+use crate::proxy::{self, Handoff, ProxyHeader, ProxyOptions};
 use crate::server::{self, Server};
 use crate::service;
 use crate::status;
 use crate::util::error::{quit_error, ErrorHints};
 
+/// Time to wait for a client handshake when peeking it to route the connection.
+const PEEK_TIMEOUT: Duration = Duration::from_secs(5);
+
 /// Start lazymc.
 ///
 /// Main entrypoint to start all server/status/proxy logic.
 ///
 /// Spawns a tokio runtime to complete all work on.
-#[tokio::main(flavor = "multi_thread")]
-pub async fn service(config: Arc<Config>) -> Result<(), ()> {
+pub fn service(config: Arc<Config>) -> Result<(), ()> {
+    let mut builder = runtime::Builder::new_multi_thread();
+    builder.enable_all();
+
+    // Pin runtime threads to configured CPU cores
+    if !config.advanced.cpu_affinity.is_empty() {
+        #[cfg(target_os = "linux")]
+        {
+            // Pin this thread first, the main task runs on it
+            if os::set_cpu_affinity(&config.advanced.cpu_affinity) {
+                let cores = config.advanced.cpu_affinity.clone();
+                builder.on_thread_start(move || {
+                    os::set_cpu_affinity(&cores);
+                });
+            } else {
+                warn!(target: "lazymc", "Failed to set CPU affinity to {:?}, continuing", config.advanced.cpu_affinity);
+            }
+        }
+
+        #[cfg(not(target_os = "linux"))]
+        warn!(target: "lazymc", "Setting CPU affinity is only supported on Linux, ignoring");
+    }
+
+    let runtime = builder.build().map_err(|err| {
+        quit_error(
+            anyhow!(err).context("Failed to start async runtime"),
+            ErrorHints::default(),
+        );
+    })?;
+
+    runtime.block_on(serve(config))
+}
+
+/// Start all server/status/proxy logic on the runtime.
+async fn serve(config: Arc<Config>) -> Result<(), ()> {
     // Load server state
     let server = Arc::new(Server::default());
 
@@ -36,7 +77,7 @@ pub async fn service(config: Arc<Config>) -> Result<(), ()> {
     info!(
         target: "lazymc",
         "Proxying public {} to server {}",
-        config.public.address, config.server.address,
+        config.public.address, config.server.address(),
     );
 
     if config.lockout.enabled {
@@ -46,17 +87,29 @@ pub async fn service(config: Arc<Config>) -> Result<(), ()> {
         );
     }
 
-    // Spawn services: monitor, signal handler
+    if config.honeypot.enabled {
+        warn!(
+            target: "lazymc",
+            "Honeypot mode is enabled, clients are kept in a fake lobby and the server is never woken",
+        );
+    }
+
+    // Spawn services: monitor, signal handler, metrics, warm ping
     tokio::spawn(service::monitor::service(config.clone(), server.clone()));
     tokio::spawn(service::signal::service(config.clone(), server.clone()));
+    tokio::spawn(service::metrics::service(config.clone(), server.clone()));
+    tokio::spawn(service::metrics::endpoint(config.clone(), server.clone()));
+    tokio::spawn(service::warm_ping::service(config.clone(), server.clone()));
 
     // Initiate server start
-    if config.server.wake_on_start {
+    if config.server.wake_on_start && !config.honeypot.enabled {
         Server::start(config.clone(), server.clone(), None).await;
     }
 
     // Spawn additional services: probe and ban manager
-    tokio::spawn(service::probe::service(config.clone(), server.clone()));
+    if !config.honeypot.enabled {
+        tokio::spawn(service::probe::service(config.clone(), server.clone()));
+    }
     tokio::task::spawn_blocking({
         let (config, server) = (config.clone(), server.clone());
         || service::file_watcher::service(config, server)
@@ -82,23 +135,110 @@ fn route(inbound: TcpStream, config: Arc<Config>, server: Arc<Server>) {
         }
     };
 
+    // Start reverse DNS lookup early, so the hostname may be known when logging
+    let peer_name = server.display_ip(&config, peer.ip());
+
     // Check ban state, just drop connection if enabled
     let banned = server.is_banned_ip_blocking(&peer.ip());
     if banned && config.server.drop_banned_ips {
-        info!(target: "lazymc", "Connection from banned IP {}, dropping", peer.ip());
+        info!(target: "lazymc", "Connection from banned IP {}, dropping", peer_name);
         return;
     }
 
+    // Serve status ourselves if server is empty and we have a MOTD or favicon for it
+    let serve_empty = config.motd.has_empty() && server.is_empty_blocking();
+
     // Route connection through proper channel
-    let should_proxy =
-        !banned && server.state() == server::State::Started && !config.lockout.enabled;
-    if should_proxy {
-        route_proxy(inbound, config)
+    let may_proxy = !banned && !config.lockout.enabled && !config.honeypot.enabled;
+    let should_proxy = may_proxy && server.state() == server::State::Started && !serve_empty;
+
+    // Peek handshake to route by hostname, to record client activity, or to serve some
+    // connections ourselves while online, see `route_peek`
+    let should_route_host = may_proxy && config.server.has_routes();
+    let should_peek = should_route_host
+        || should_proxy
+            && (config.join.max_logins_per_minute > 0
+                || config.join.queue.enabled
+                || config.motd.proxy_when_running
+                || config.server.has_client_activity());
+    if should_peek {
+        tokio::spawn(route_peek(
+            inbound,
+            config,
+            server,
+            peer,
+            should_route_host,
+            should_proxy,
+        ));
+    } else if should_proxy {
+        status::log_connection_summary(&config, &server, peer.ip(), None, status::Intent::Proxy);
+        route_proxy(inbound, config, &server)
     } else {
         route_status(inbound, config, server, peer)
     }
 }
 
+/// Peek handshake of inbound TCP stream, route to other server, status server or proxy.
+///
+/// If `route_host` is set, clients connecting with a configured hostname are proxied to the other
+/// server it routes to. Other clients are only proxied to our server if `proxy` is set.
+///
+/// Logins are routed to the status server if throttled or queued, to apply the login throttle or
+/// join queue. Status requests are routed to the status server if relaying live status, it relays
+/// the live server status or falls back to the cached status. Everything else is proxied, also if
+/// the client doesn't send a valid handshake in time. Proxied logins and status requests are
+/// recorded as activity.
+async fn route_peek(
+    inbound: TcpStream,
+    config: Arc<Config>,
+    server: Arc<Server>,
+    peer: SocketAddr,
+    route_host: bool,
+    proxy: bool,
+) {
+    let handshake = peek::peek_handshake(&inbound, PEEK_TIMEOUT).await;
+
+    // Proxy to other server if routed by hostname
+    if let Some(handshake) = handshake.as_ref().filter(|_| route_host) {
+        let hostname = handshake.hostname();
+        if let Some(addr) = config.server.route(&hostname) {
+            debug!(target: "lazymc", "Routing {} for hostname {} to {}", server.display_ip(&config, peer.ip()), hostname, addr);
+            route_proxy_host(inbound, config, addr);
+            return;
+        }
+    }
+
+    let next_state = handshake.map(|handshake| handshake.next_state);
+    let serve = match next_state {
+        _ if !proxy => true,
+        Some(ClientState::Login) => {
+            config.join.max_logins_per_minute > 0 || config.join.queue.enabled
+        }
+        Some(ClientState::Status) => config.motd.proxy_when_running,
+        _ => false,
+    };
+
+    if serve {
+        route_status(inbound, config, server, peer)
+    } else {
+        // Record activity, the status server records it for clients it serves
+        match next_state {
+            Some(ClientState::Login) => {
+                server.record_activity(&config, ActivitySource::Login).await
+            }
+            Some(ClientState::Status) => {
+                server
+                    .record_activity(&config, ActivitySource::Status)
+                    .await
+            }
+            _ => {}
+        }
+
+        status::log_connection_summary(&config, &server, peer.ip(), None, status::Intent::Proxy);
+        route_proxy(inbound, config, &server)
+    }
+}
+
 /// Route inbound TCP stream to status server, spawning a new task.
 #[inline]
 fn route_status(inbound: TcpStream, config: Arc<Config>, server: Arc<Server>, peer: SocketAddr) {
@@ -115,44 +255,95 @@ fn route_status(inbound: TcpStream, config: Arc<Config>, server: Arc<Server>, pe
 
 /// Route inbound TCP stream to proxy, spawning a new task.
 #[inline]
-fn route_proxy(inbound: TcpStream, config: Arc<Config>) {
+fn route_proxy(inbound: TcpStream, config: Arc<Config>, server: &Server) {
     // When server is online, proxy all
-    let service = proxy::proxy(
-        inbound,
-        ProxyHeader::Proxy.not_none(config.server.send_proxy_v2),
-        config.server.address,
-    )
-    .map(|r| {
-        if let Err(err) = r {
-            warn!(target: "lazymc", "Failed to proxy: {}", err);
-        }
-    });
+    let addrs = server.proxy_addresses(&config);
+    let options = ProxyOptions::from_config(&config, &server.proxy_activity);
+    let service = async move {
+        proxy::proxy(
+            inbound,
+            ProxyHeader::Proxy.not_none(config.server.send_proxy_v2),
+            &addrs,
+            options,
+        )
+        .map(|r| {
+            if let Err(err) = r {
+                warn!(target: "lazymc", "Failed to proxy: {}", err);
+            }
+        })
+        .await
+    };
+
+    tokio::spawn(service);
+}
+
+/// Route inbound TCP stream to proxy for other server routed to by hostname, spawning a new task.
+///
+/// The other server is not managed by lazymc, its connections don't count as activity.
+#[inline]
+fn route_proxy_host(inbound: TcpStream, config: Arc<Config>, addr: SocketAddr) {
+    let options = ProxyOptions {
+        idle_timeout: match config.advanced.proxy_idle_timeout {
+            0 => None,
+            secs => Some(Duration::from_secs(secs as u64)),
+        },
+        connect_timeout: config.server.connect_timeout(),
+        ..Default::default()
+    };
+    let service = async move {
+        proxy::proxy(
+            inbound,
+            ProxyHeader::Proxy.not_none(config.server.send_proxy_v2),
+            &[addr],
+            options,
+        )
+        .map(|r| {
+            if let Err(err) = r {
+                warn!(target: "lazymc", "Failed to proxy to {}: {}", addr, err);
+            }
+        })
+        .await
+    };
 
     tokio::spawn(service);
 }
 
 /// Route inbound TCP stream to proxy with queued data, spawning a new task.
+///
+/// Times the handoff of the joining client, `cold_started` tells whether it waited for the server
+/// to start.
 #[inline]
-pub fn route_proxy_queue(inbound: TcpStream, config: Arc<Config>, queue: BytesMut) {
+pub fn route_proxy_queue(
+    inbound: TcpStream,
+    config: Arc<Config>,
+    server: &Server,
+    queue: BytesMut,
+    cold_started: bool,
+) {
+    let mut options = ProxyOptions::from_config(&config, &server.proxy_activity);
+    options.handoff = Some(Handoff::start(&config, &server.metrics, cold_started));
+
     route_proxy_address_queue(
         inbound,
         ProxyHeader::Proxy.not_none(config.server.send_proxy_v2),
-        config.server.address,
+        server.proxy_addresses(&config),
         queue,
+        options,
     );
 }
 
-/// Route inbound TCP stream to proxy with given address and queued data, spawning a new task.
+/// Route inbound TCP stream to proxy with given addresses and queued data, spawning a new task.
 #[inline]
 pub fn route_proxy_address_queue(
     inbound: TcpStream,
     proxy_header: ProxyHeader,
-    addr: SocketAddr,
+    addrs: Vec<SocketAddr>,
     queue: BytesMut,
+    options: ProxyOptions,
 ) {
     // When server is online, proxy all
     let service = async move {
-        proxy::proxy_with_queue(inbound, proxy_header, addr, &queue)
+        proxy::proxy_with_queue(inbound, proxy_header, &addrs, &queue, options)
             .map(|r| {
                 if let Err(err) = r {
                     warn!(target: "lazymc", "Failed to proxy: {}", err);
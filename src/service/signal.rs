@@ -1,18 +1,73 @@
 use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::time;
 
 use crate::config::Config;
 use crate::server::{self, Server};
 use crate::util::error;
 
+/// Maximum time to wait for proxied connections to close before quitting.
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Interval to check whether proxied connections closed while draining.
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Maximum time to wait for a server without known process to stop on SIGTERM, if
+/// `server.stop_timeout` is disabled.
+#[cfg(unix)]
+const TERMINATE_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Signal received by lazymc.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Signal {
+    /// SIGINT, or Ctrl-C.
+    Interrupt,
+
+    /// SIGTERM, as sent by init systems.
+    #[cfg_attr(not(unix), allow(dead_code))]
+    Terminate,
+}
+
 /// Signal handler task.
 pub async fn service(config: Arc<Config>, server: Arc<Server>) {
+    #[cfg(unix)]
+    let mut terminate = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+
     loop {
         // Wait for SIGTERM/SIGINT signal
-        tokio::signal::ctrl_c().await.unwrap();
+        #[cfg(unix)]
+        let signal = tokio::select! {
+            result = tokio::signal::ctrl_c() => {
+                result.unwrap();
+                Signal::Interrupt
+            }
+            _ = terminate.recv() => Signal::Terminate,
+        };
+        #[cfg(not(unix))]
+        let signal = {
+            tokio::signal::ctrl_c().await.unwrap();
+            Signal::Interrupt
+        };
+
+        // On terminate, always stop server, even if frozen, and quit once it stopped
+        #[cfg(unix)]
+        if signal == Signal::Terminate {
+            terminate(&config, &server).await;
+            quit(&server).await;
+        }
 
         // Quit if stopped
         if server.state() == server::State::Stopped {
-            quit();
+            quit(&server).await;
+        }
+
+        // Leave server running on interrupt if configured
+        if signal == Signal::Interrupt && !config.advanced.sigint_stops_backend {
+            info!(target: "lazymc", "Quitting, leaving server running");
+            quit(&server).await;
         }
 
         // Try to stop server
@@ -20,13 +75,189 @@ pub async fn service(config: Arc<Config>, server: Arc<Server>) {
 
         // If not stopping, maybe due to failure, just quit
         if !stopping {
-            quit();
+            quit(&server).await;
+        }
+    }
+}
+
+/// Stop server on SIGTERM, and wait for it to stop.
+///
+/// Stops the server process, unfreezing it first if frozen. Servers without known process, such as
+/// an adopted external server, are stopped through RCON instead.
+#[cfg(unix)]
+async fn terminate(config: &Config, server: &Server) {
+    if server.terminate(config).await {
+        info!(target: "lazymc", "Waiting for server to stop before quitting...");
+        server.wait_for_exit().await;
+        return;
+    }
+
+    // No known server process, stop through RCON if online
+    if server.state() != server::State::Started || !server.stop(config).await {
+        return;
+    }
+
+    info!(target: "lazymc", "Waiting for server to stop before quitting...");
+    let timeout = match config.server.stop_timeout {
+        0 => TERMINATE_TIMEOUT,
+        secs => Duration::from_secs(secs as u64),
+    };
+    if time::timeout(timeout, wait_for_stopped(server))
+        .await
+        .is_err()
+    {
+        warn!(target: "lazymc", "Server did not stop in time, quitting anyway");
+    }
+}
+
+/// Wait until the server state is stopped.
+#[cfg(unix)]
+async fn wait_for_stopped(server: &Server) {
+    let mut state = server.state_receiver();
+    while *state.borrow() != server::State::Stopped {
+        if state.changed().await.is_err() {
+            return;
         }
     }
 }
 
-/// Gracefully quit.
-fn quit() -> ! {
-    // TODO: gracefully quit self
-    error::quit();
+/// Gracefully quit, after draining proxied connections.
+///
+/// Never returns.
+async fn quit(server: &Server) {
+    drain(server).await;
+    error::quit()
+}
+
+/// Wait for open proxied connections to close, up to `DRAIN_TIMEOUT`.
+///
+/// Stops waiting early on another Ctrl-C.
+async fn drain(server: &Server) {
+    let activity = &server.proxy_activity;
+    if activity.open_count() == 0 {
+        return;
+    }
+
+    info!(target: "lazymc", "Waiting up to {}s for {} proxied connection(s) to close, press Ctrl-C to quit now...", DRAIN_TIMEOUT.as_secs(), activity.open_count());
+    let closed = async {
+        while activity.open_count() > 0 {
+            time::sleep(DRAIN_POLL_INTERVAL).await;
+        }
+    };
+    tokio::select! {
+        result = time::timeout(DRAIN_TIMEOUT, closed) => {
+            if result.is_err() {
+                warn!(target: "lazymc", "Proxied connections did not close in time, quitting anyway");
+            }
+        }
+        _ = tokio::signal::ctrl_c() => {}
+    }
+}
+
+#[cfg(all(test, unix, feature = "rcon"))]
+mod tests {
+    use super::*;
+    use minecraft_protocol::data::chat::{Message, Payload};
+    use minecraft_protocol::data::server_status::{OnlinePlayers, ServerStatus, ServerVersion};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::oneshot;
+
+    /// RCON packet type of authentication response and command.
+    const RCON_AUTH_RESPONSE: i32 = 2;
+
+    /// RCON packet type of command response.
+    const RCON_RESPONSE: i32 = 0;
+
+    /// RCON packet type of authentication request.
+    const RCON_AUTH: i32 = 3;
+
+    /// Fake RCON server, accepts any password and responds to every command with nothing.
+    ///
+    /// Notifies once the `stop` command is received.
+    async fn fake_rcon(listener: TcpListener, stopped: oneshot::Sender<()>) {
+        let mut stopped = Some(stopped);
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            loop {
+                let len = match stream.read_i32_le().await {
+                    Ok(len) => len as usize,
+                    Err(_) => break,
+                };
+                let mut packet = vec![0; len];
+                stream.read_exact(&mut packet).await.unwrap();
+                let id = i32::from_le_bytes(packet[0..4].try_into().unwrap());
+                let kind = i32::from_le_bytes(packet[4..8].try_into().unwrap());
+                let body = &packet[8..len - 2];
+
+                let kind = match kind {
+                    RCON_AUTH => RCON_AUTH_RESPONSE,
+                    _ => RCON_RESPONSE,
+                };
+                let mut response = 10i32.to_le_bytes().to_vec();
+                response.extend(id.to_le_bytes());
+                response.extend(kind.to_le_bytes());
+                response.extend([0, 0]);
+                stream.write_all(&response).await.unwrap();
+
+                if body == b"stop" {
+                    if let Some(stopped) = stopped.take() {
+                        let _ = stopped.send(());
+                    }
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn terminate_without_process_through_rcon() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let config: Config = toml::from_str(&format!(
+            "[server]\ncommand = \"true\"\naddress = \"127.0.0.1:25566\"\n[rcon]\nenabled = true\nport = {}\npassword = \"test\"\nrandomize_password = false",
+            listener.local_addr().unwrap().port()
+        ))
+        .unwrap();
+        let (stopped_tx, stopped_rx) = oneshot::channel();
+        tokio::spawn(fake_rcon(listener, stopped_tx));
+
+        // Server started outside of lazymc is adopted, its process is unknown
+        let server = Arc::new(Server::default());
+        let status = ServerStatus {
+            version: ServerVersion {
+                name: "1.20.4".into(),
+                protocol: 765,
+            },
+            description: Message::new(Payload::text("A Minecraft Server")),
+            players: OnlinePlayers {
+                online: 0,
+                max: 20,
+                sample: vec![],
+            },
+            favicon: None,
+        };
+        server.update_status(&config, Some(status)).await;
+        assert_eq!(server.state(), server::State::Started);
+
+        let config = Arc::new(config);
+        let task = tokio::spawn({
+            let (config, server) = (config.clone(), server.clone());
+            async move { terminate(&config, &server).await }
+        });
+
+        // Stopped through RCON, waits until server is offline
+        time::timeout(Duration::from_secs(10), stopped_rx)
+            .await
+            .unwrap()
+            .unwrap();
+        time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(server.state(), server::State::Stopping);
+        assert!(!task.is_finished());
+
+        server.update_status(&config, None).await;
+        time::timeout(Duration::from_secs(5), task)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(server.state(), server::State::Stopped);
+    }
 }
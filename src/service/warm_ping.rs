@@ -0,0 +1,40 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::time;
+
+use crate::config::Config;
+use crate::monitor;
+use crate::server::{Server, State};
+
+/// Warm ping task.
+///
+/// Periodically sends a login to the server while it is online without players, to keep its JIT
+/// and caches warm. Warm pings are never counted as activity.
+pub async fn service(config: Arc<Config>, server: Arc<Server>) {
+    // Warm pings must be enabled
+    if config.server.warm_ping_interval == 0 {
+        return;
+    }
+
+    let mut interval = time::interval(Duration::from_secs(config.server.warm_ping_interval as u64));
+    interval.tick().await;
+
+    loop {
+        interval.tick().await;
+
+        // Server must be online without players
+        if server.state() != State::Started || !server.is_empty().await {
+            continue;
+        }
+
+        match monitor::warm_ping(&config, &server.poll_addresses(&config)).await {
+            Ok(true) => trace!(target: "lazymc::monitor", "Sent warm ping to server"),
+            Ok(false) => {
+                warn!(target: "lazymc::monitor", "Server accepted warm ping login, it is likely in offline mode, disabling warm pings");
+                return;
+            }
+            Err(()) => debug!(target: "lazymc::monitor", "Failed to send warm ping to server"),
+        }
+    }
+}
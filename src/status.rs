@@ -1,38 +1,93 @@
-use std::sync::Arc;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 use bytes::BytesMut;
+use chrono::Local;
+use futures::future;
 use minecraft_protocol::data::chat::{Message, Payload};
 use minecraft_protocol::data::server_status::*;
 use minecraft_protocol::decoder::Decoder;
 use minecraft_protocol::encoder::Encoder;
 use minecraft_protocol::version::v1_14_4::handshake::Handshake;
-use minecraft_protocol::version::v1_14_4::login::LoginStart;
 use minecraft_protocol::version::v1_14_4::status::StatusResponse;
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
+use tokio::net::tcp::WriteHalf;
 use tokio::net::TcpStream;
+use tokio::sync::watch;
+use tokio::time;
 
-use crate::config::{Config, Server as ConfigServer};
+use crate::config::{
+    ActivitySource, Config, MotdFormat, Server as ConfigServer, UnknownProtocolBehavior,
+};
 use crate::join;
-use crate::mc::favicon;
+#[cfg(feature = "lobby")]
+use crate::lobby;
+use crate::mc::{favicon, minimessage};
+use crate::net;
 use crate::proto::action;
 use crate::proto::client::{Client, ClientInfo, ClientState};
 use crate::proto::packet::{self, RawPacket};
-use crate::proto::packets;
+use crate::proto::packets::{self, login_start::LoginStartData};
+use crate::proxy;
 use crate::server::{self, Server};
+use crate::service;
+use crate::types;
+use crate::util::time::format_duration;
 
 /// The ban message prefix.
 const BAN_MESSAGE_PREFIX: &str = "Your IP address is banned from this server.\nReason: ";
 
+/// The player ban message prefix.
+const PLAYER_BAN_MESSAGE_PREFIX: &str = "You are banned from this server.\nReason: ";
+
 /// Default ban reason if unknown.
 const DEFAULT_BAN_REASON: &str = "Banned by an operator.";
 
 /// The not-whitelisted kick message.
 const WHITELIST_MESSAGE: &str = "You are not white-listed on this server!";
 
+/// The restart cooldown kick message.
+const RESTART_COOLDOWN_MESSAGE: &str =
+    "Server just stopped §7☠§r\n\nPlease try to reconnect shortly to wake it again.";
+
+/// The kick message when the server is running outside of lazymc and not adopted.
+const UNADOPTED_EXTERNAL_MESSAGE: &str =
+    "Server is running outside of lazymc §7☠§r\n\nPlease try to reconnect later.";
+
+/// The per-IP wake cooldown kick message.
+const IP_WAKE_COOLDOWN_MESSAGE: &str =
+    "Server is sleeping §7☠§r\n\nYou woke it up recently, please try again later.";
+
+/// The kick message for login start not matching handshake protocol version.
+const PROTOCOL_MISMATCH_MESSAGE: &str =
+    "Your client sent a login that does not match its protocol version.\n\nPlease use an unmodified client.";
+
+/// The login throttle kick message.
+const LOGIN_THROTTLE_MESSAGE: &str =
+    "Too many players are logging in.\n\nPlease wait a moment and try to reconnect.";
+
+/// MOTD placeholders for server uptime and sleeping time.
+const MOTD_UPTIME: &str = "{uptime}";
+const MOTD_ASLEEP_FOR: &str = "{asleep_for}";
+
+/// MOTD placeholder value if unknown.
+const MOTD_UNKNOWN: &str = "unknown";
+
+/// Time a client must be idle for before dropping it when the server starts.
+const DROP_IDLE_AFTER: Duration = Duration::from_secs(2);
+
+/// Timeout for fetching live status from server.
+const LIVE_STATUS_TIMEOUT: Duration = Duration::from_secs(2);
+
 /// Server icon file path.
 const SERVER_ICON_FILE: &str = "server-icon.png";
 
+/// Nil UUID, used for player sample entries.
+const NIL_UUID: &str = "00000000-0000-0000-0000-000000000000";
+
 /// Proxy the given inbound stream to a target address.
 // TODO: do not drop error here, return Box<dyn Error>
 pub async fn serve(
@@ -49,10 +104,43 @@ pub async fn serve(
     // Remember inbound packets, track client info
     let mut inbound_history = BytesMut::new();
     let mut client_info = ClientInfo::empty();
+    let mut handshake_raw = vec![];
+
+    // Track client activity to drop idle connections when server starts
+    let mut state_receiver = server.state_receiver();
+    let mut last_read = Instant::now();
+
+    // Drop connection if client sends nothing after connecting, such as scanners holding it open
+    if config.advanced.idle_connect_timeout > 0 {
+        let timeout = Duration::from_secs(config.advanced.idle_connect_timeout as u64);
+        match time::timeout(timeout, reader.peek(&mut [0u8])).await {
+            Ok(Ok(0)) => return Ok(()),
+            Ok(_) => {}
+            Err(_) => {
+                debug!(target: "lazymc", "Closing connection, client sent nothing for {}s", timeout.as_secs());
+                return Ok(());
+            }
+        }
+    }
 
     loop {
-        // Read packet from stream
-        let (packet, raw) = match packet::read_packet(&client, &mut buf, &mut reader).await {
+        // Read packet from stream, drop idle connection if server starts and configured
+        let droppable = is_droppable(&config, client.state(), &buf);
+        let read = packet::read_packet(&client, &mut buf, &mut reader);
+        let result = if droppable {
+            tokio::select! {
+                result = read => result,
+                _ = idle_while_starting(&mut state_receiver, last_read) => {
+                    debug!(target: "lazymc", "Closing idle connection, server is starting");
+                    break;
+                }
+            }
+        } else {
+            read.await
+        };
+        last_read = Instant::now();
+
+        let (packet, raw) = match result {
             Ok(Some(packet)) => packet,
             Ok(None) => break,
             Err(_) => {
@@ -64,24 +152,52 @@ pub async fn serve(
         // Grab client state
         let client_state = client.state();
 
+        // Client must start with handshake, status request without handshake is a protocol error
+        if client_state == ClientState::Handshake
+            && packet.id == packets::status::SERVER_STATUS
+            && packet.data.is_empty()
+        {
+            debug!(target: "lazymc", "Got status request from client before handshake, disconnecting");
+            server.metrics.handshake_errors.incr(None);
+            break;
+        }
+
         // Hijack handshake
         if client_state == ClientState::Handshake
             && packet.id == packets::handshake::SERVER_HANDSHAKE
         {
             // Parse handshake
-            let handshake = match Handshake::decode(&mut packet.data.as_slice()) {
-                Ok(handshake) => handshake,
-                Err(_) => {
-                    debug!(target: "lazymc", "Got malformed handshake from client, disconnecting");
+            let handshake = match Handshake::decode(&mut packet.data.as_slice())
+                .ok()
+                .or_else(|| recover_handshake(&packet.data))
+            {
+                Some(handshake) => handshake,
+                None => {
+                    debug!(target: "lazymc", "Got malformed or truncated handshake from client, dropping invalid probe");
+                    let protocol = types::read_var_int(&packet.data)
+                        .ok()
+                        .map(|(_, protocol)| protocol as u32);
+                    server.metrics.handshake_errors.incr(protocol);
                     break;
                 }
             };
 
-            // Parse new state
+            // Drop invalid probes, such as from scanners, protocol -1 is used by pingers and valid
+            if handshake.protocol_version == 0 {
+                debug!(target: "lazymc", "Got handshake with protocol version 0 from client, dropping invalid probe");
+                server.metrics.handshake_errors.incr(Some(0));
+                break;
+            }
+
+            // Parse new state, must move out of handshake state
             let new_state = match ClientState::from_id(handshake.next_state) {
-                Some(state) => state,
-                None => {
-                    error!(target: "lazymc", "Client tried to switch into unknown protcol state ({}), disconnecting", handshake.next_state);
+                Some(state) if state != ClientState::Handshake => state,
+                _ => {
+                    debug!(target: "lazymc", "Client tried to switch into invalid protocol state ({}), dropping invalid probe", handshake.next_state);
+                    server
+                        .metrics
+                        .handshake_errors
+                        .incr(Some(handshake.protocol_version as u32));
                     break;
                 }
             };
@@ -92,6 +208,7 @@ pub async fn serve(
                 .replace(handshake.protocol_version as u32);
             client_info.handshake.replace(handshake);
             client.set_state(new_state);
+            handshake_raw = raw.clone();
 
             // If loggin in with handshake, remember inbound
             if new_state == ClientState::Login {
@@ -103,6 +220,50 @@ pub async fn serve(
 
         // Hijack server status packet
         if client_state == ClientState::Status && packet.id == packets::status::SERVER_STATUS {
+            // Status request has no data, otherwise it is likely a repeated handshake
+            if !packet.data.is_empty() {
+                debug!(target: "lazymc", "Got repeated handshake or malformed status request from client, disconnecting");
+                server.metrics.handshake_errors.incr(client_info.protocol());
+                break;
+            }
+
+            // Disconnect if protocol version is unknown and configured
+            if config.public.unknown_protocol_behavior == UnknownProtocolBehavior::Disconnect
+                && client_info.is_protocol_unknown()
+            {
+                debug!(target: "lazymc", "Got status request from client with unknown protocol version, disconnecting");
+                break;
+            }
+
+            server
+                .record_activity(&config, ActivitySource::Status)
+                .await;
+
+            // Relay live status from server if online and configured, unless using empty status
+            if config.motd.proxy_when_running
+                && server.state() == server::State::Started
+                && !(config.motd.has_empty() && server.is_empty().await)
+            {
+                match fetch_live_status(&config, &server, &handshake_raw, &raw).await {
+                    Ok(response) => {
+                        if !write_response(&mut writer, &response).await? {
+                            break;
+                        }
+                        log_connection_summary(
+                            &config,
+                            &server,
+                            client.peer.ip(),
+                            Some(&client_info),
+                            Intent::Status,
+                        );
+                        continue;
+                    }
+                    Err(()) => {
+                        debug!(target: "lazymc", "Failed to relay live server status, using cached status")
+                    }
+                }
+            }
+
             let server_status = server_status(&client_info, &config, &server).await;
             let packet = StatusResponse { server_status };
 
@@ -110,26 +271,113 @@ pub async fn serve(
             packet.encode(&mut data).map_err(|_| ())?;
 
             let response = RawPacket::new(0, data).encode_with_len(&client)?;
-            writer.write_all(&response).await.map_err(|_| ())?;
+            if !write_response(&mut writer, &response).await? {
+                break;
+            }
+            log_connection_summary(
+                &config,
+                &server,
+                client.peer.ip(),
+                Some(&client_info),
+                Intent::Status,
+            );
 
             continue;
         }
 
         // Hijack ping packet
         if client_state == ClientState::Status && packet.id == packets::status::SERVER_PING {
-            writer.write_all(&raw).await.map_err(|_| ())?;
+            if !write_response(&mut writer, &raw).await? {
+                break;
+            }
             continue;
         }
 
+        // Status request after switching to login is a protocol error, it would start the server
+        if client_state == ClientState::Login
+            && packet.id == packets::status::SERVER_STATUS
+            && packet.data.is_empty()
+        {
+            debug!(target: "lazymc", "Got status request from client in login state, disconnecting");
+            break;
+        }
+
+        // Client must not send another handshake, it has the same packet ID as login start
+        if client_state == ClientState::Login
+            && packet.id == packets::login::SERVER_LOGIN_START
+            && is_repeated_handshake(&client_info, &packet.data)
+        {
+            debug!(target: "lazymc", "Got repeated handshake from client, disconnecting");
+            server.metrics.handshake_errors.incr(client_info.protocol());
+            break;
+        }
+
         // Hijack login start
         if client_state == ClientState::Login && packet.id == packets::login::SERVER_LOGIN_START {
-            // Try to get login username, update client info
-            // TODO: we should always parse this packet successfully
-            let username = LoginStart::decode(&mut packet.data.as_slice())
-                .ok()
-                .map(|p| p.name);
+            // Try to get login username and UUID, update client info
+            // Continue without them on failure, the server handles a malformed login itself
+            let login_start = LoginStartData::decode(&client_info, &packet.data).ok();
+            if login_start.is_none() {
+                debug!(target: "lazymc", "Got malformed login start from client, username unknown");
+                server
+                    .metrics
+                    .login_start_errors
+                    .incr(client_info.protocol());
+            }
+            let username = login_start
+                .as_ref()
+                .map(|login_start| login_start.name.clone());
             client_info.username = username.clone();
 
+            // Keep client in fake lobby if honeypot is enabled, never wakes server
+            if config.honeypot.enabled {
+                let ip = server.display_ip(&config, client.peer.ip());
+                let protocol = client_info.protocol().map(|p| p.to_string());
+                let address = client_info
+                    .handshake
+                    .as_ref()
+                    .map(|h| format!("{}:{}", h.server_addr, h.server_port));
+                info!(target: "lazymc::honeypot", "Login from {} (user: {}, protocol: {}, address: {})", ip, username.as_deref().unwrap_or("unknown"), protocol.as_deref().unwrap_or("unknown"), address.as_deref().unwrap_or("unknown"));
+
+                #[cfg(feature = "lobby")]
+                {
+                    let mut queue = BytesMut::with_capacity(raw.len() + buf.len());
+                    queue.extend(&raw);
+                    queue.extend(&buf);
+                    return lobby::serve_honeypot(
+                        &client,
+                        client_info,
+                        inbound,
+                        config,
+                        server,
+                        queue,
+                    )
+                    .await;
+                }
+
+                #[cfg(not(feature = "lobby"))]
+                {
+                    action::kick(&client, &config.honeypot.message, &mut writer).await?;
+                    break;
+                }
+            }
+
+            // Kick if login start doesn't match handshake protocol version, likely a broken client
+            if config.join.strict_protocol_consistency
+                && !login_start
+                    .as_ref()
+                    .map(|login_start| login_start.matches_protocol)
+                    .unwrap_or(false)
+            {
+                info!(target: "lazymc", "Kicked '{}' because login does not match protocol version {}", username.as_deref().unwrap_or("unknown"), client_info.protocol().map(|p| p.to_string()).as_deref().unwrap_or("unknown"));
+                server
+                    .metrics
+                    .login_start_errors
+                    .incr(client_info.protocol());
+                action::kick(&client, PROTOCOL_MISMATCH_MESSAGE, &mut writer).await?;
+                break;
+            }
+
             // Kick if lockout is enabled
             if config.lockout.enabled {
                 match username {
@@ -146,10 +394,10 @@ pub async fn serve(
             if let Some(ban) = server.ban_entry(&client.peer.ip()).await {
                 if ban.is_banned() {
                     let msg = if let Some(reason) = ban.reason {
-                        info!(target: "lazymc", "Login from banned IP {} ({}), disconnecting", client.peer.ip(), &reason);
+                        info!(target: "lazymc", "Login from banned IP {} ({}), disconnecting", server.display_ip(&config, client.peer.ip()), &reason);
                         reason.to_string()
                     } else {
-                        info!(target: "lazymc", "Login from banned IP {}, disconnecting", client.peer.ip());
+                        info!(target: "lazymc", "Login from banned IP {}, disconnecting", server.display_ip(&config, client.peer.ip()));
                         DEFAULT_BAN_REASON.to_string()
                     };
                     action::kick(&client, &format!("{BAN_MESSAGE_PREFIX}{msg}"), &mut writer)
@@ -158,6 +406,84 @@ pub async fn serve(
                 }
             }
 
+            // Kick if player is banned, by UUID or username
+            if let (true, Some(login_start)) = (config.bans.by_uuid, &login_start) {
+                let username = &login_start.name;
+                if let Some(ban) = server.player_ban_entry(login_start.uuid, username).await {
+                    if ban.is_banned() {
+                        let msg = match ban.reason {
+                            Some(reason) => {
+                                info!(target: "lazymc", "Login from banned player '{}' ({}), disconnecting", username, &reason);
+                                reason
+                            }
+                            None => {
+                                info!(target: "lazymc", "Login from banned player '{}', disconnecting", username);
+                                DEFAULT_BAN_REASON.to_string()
+                            }
+                        };
+                        action::kick(
+                            &client,
+                            &format!("{PLAYER_BAN_MESSAGE_PREFIX}{msg}"),
+                            &mut writer,
+                        )
+                        .await?;
+                        break;
+                    }
+                }
+            }
+
+            // Proxy to server if already online
+            if server.state() == server::State::Started {
+                // Kick if too many logins recently, protects server authentication rate limit
+                if !server.try_login(&config).await {
+                    info!(target: "lazymc", "Kicked player because too many players are logging in");
+                    action::kick(&client, LOGIN_THROTTLE_MESSAGE, &mut writer).await?;
+                    break;
+                }
+
+                server.record_activity(&config, ActivitySource::Login).await;
+                log_connection_summary(
+                    &config,
+                    &server,
+                    client.peer.ip(),
+                    Some(&client_info),
+                    Intent::Proxy,
+                );
+
+                // Queue in lobby if server is full, proxy if the lobby can't be used
+                #[cfg(feature = "lobby")]
+                if join::queue::should_queue(&config, &server).await {
+                    info!(target: "lazymc", "Server is full, queueing player");
+                    let mut login_queue = BytesMut::with_capacity(raw.len() + buf.len());
+                    login_queue.extend(&raw);
+                    login_queue.extend(&buf);
+                    match join::occupy_queue(
+                        &client,
+                        client_info.clone(),
+                        config.clone(),
+                        server.clone(),
+                        inbound,
+                        login_queue,
+                    )
+                    .await?
+                    {
+                        join::MethodResult::Consumed => return Ok(()),
+                        join::MethodResult::Continue(stream) => inbound = stream,
+                    }
+                }
+
+                inbound_history.extend(&raw);
+                inbound_history.extend(&buf);
+                service::server::route_proxy_queue(
+                    inbound,
+                    config,
+                    &server,
+                    inbound_history,
+                    false,
+                );
+                return Ok(());
+            }
+
             // Kick if client is not whitelisted to wake server
             if let Some(ref username) = username {
                 if !server.is_whitelisted(username).await {
@@ -167,8 +493,43 @@ pub async fn serve(
                 }
             }
 
+            // Kick if server runs outside of lazymc and isn't adopted, don't start another
+            if server.is_unadopted_external(&config) {
+                info!(target: "lazymc", "Kicked player because server was started outside of lazymc and is not adopted");
+                action::kick(&client, UNADOPTED_EXTERNAL_MESSAGE, &mut writer).await?;
+                break;
+            }
+
+            // Kick if server just stopped and is in restart cooldown
+            if server.state() == server::State::Stopped
+                && server.restart_cooldown(&config).await.is_some()
+            {
+                info!(target: "lazymc", "Kicked player because server is in restart cooldown");
+                action::kick(&client, RESTART_COOLDOWN_MESSAGE, &mut writer).await?;
+                break;
+            }
+
+            // Kick if server is sleeping and this IP woke it recently
+            let ip = client.peer.ip();
+            if server.state() == server::State::Stopped {
+                if let Some(remaining) = server.ip_wake_cooldown(&config, &ip).await {
+                    info!(target: "lazymc", "Kicked player from {} because IP is in wake cooldown for {}s", server.display_ip(&config, ip), remaining.as_secs());
+                    action::kick(&client, IP_WAKE_COOLDOWN_MESSAGE, &mut writer).await?;
+                    break;
+                }
+            }
+
+            // Kick if too many logins recently, protects server authentication rate limit
+            if !server.try_login(&config).await {
+                info!(target: "lazymc", "Kicked player because too many players are logging in");
+                action::kick(&client, LOGIN_THROTTLE_MESSAGE, &mut writer).await?;
+                break;
+            }
+
             // Start server if not starting yet
-            Server::start(config.clone(), server.clone(), username).await;
+            if Server::start(config.clone(), server.clone(), username).await {
+                server.record_ip_wake(&config, ip).await;
+            }
 
             // Remember inbound packets
             inbound_history.extend(&raw);
@@ -183,6 +544,13 @@ pub async fn serve(
             buf.clear();
 
             // Start occupying client
+            log_connection_summary(
+                &config,
+                &server,
+                client.peer.ip(),
+                Some(&client_info),
+                Intent::Login,
+            );
             join::occupy(
                 client,
                 client_info,
@@ -197,22 +565,248 @@ pub async fn serve(
         }
 
         // Show unhandled packet warning
+        server
+            .metrics
+            .unhandled_packets
+            .incr(client_info.protocol());
         debug!(target: "lazymc", "Got unhandled packet:");
         debug!(target: "lazymc", "- State: {:?}", client_state);
         debug!(target: "lazymc", "- Packet ID: {}", packet.id);
+
+        // Only a handshake is valid before handshaking, disconnect instead of waiting for more
+        if client_state == ClientState::Handshake {
+            debug!(target: "lazymc", "Client did not start with handshake, disconnecting");
+            break;
+        }
+
+        // Only a login start is valid in login state, disconnect instead of waiting for more
+        if client_state == ClientState::Login {
+            debug!(target: "lazymc", "Client did not send login start, disconnecting");
+            break;
+        }
     }
 
     Ok(())
 }
 
+/// Connection intent, for connection summary log.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Intent {
+    /// Client requested server status.
+    Status,
+
+    /// Client logged in while server is not online.
+    Login,
+
+    /// Client was proxied to online server.
+    Proxy,
+}
+
+impl Intent {
+    /// Get intent name.
+    fn name(self) -> &'static str {
+        match self {
+            Self::Status => "status",
+            Self::Login => "login",
+            Self::Proxy => "proxy",
+        }
+    }
+}
+
+/// Log single line connection summary if enabled.
+///
+/// Status requests are logged at debug level, to not spam the log with server list refreshes.
+/// Client info is `None` if connection is proxied without inspecting it.
+pub fn log_connection_summary(
+    config: &Config,
+    server: &Server,
+    ip: IpAddr,
+    client_info: Option<&ClientInfo>,
+    intent: Intent,
+) {
+    if !config.advanced.connection_summary_log {
+        return;
+    }
+
+    let level = match intent {
+        Intent::Status => log::Level::Debug,
+        Intent::Login | Intent::Proxy => log::Level::Info,
+    };
+    let username = client_info.and_then(|info| info.username.as_deref());
+    let protocol = client_info.and_then(|info| info.protocol());
+    log!(
+        target: "lazymc::connection",
+        level,
+        "Connection from {}: {} (user: {}, protocol: {})",
+        server.display_ip(config, ip),
+        intent.name(),
+        username.unwrap_or("unknown"),
+        protocol.map(|p| p.to_string()).as_deref().unwrap_or("unknown"),
+    );
+}
+
+/// Write status or ping response to client.
+///
+/// Returns `Ok(false)` if the client closed the connection, which is normal for server list
+/// clients closing early after reading what they need. Fails on other write errors.
+async fn write_response(writer: &mut WriteHalf<'_>, response: &[u8]) -> Result<bool, ()> {
+    match writer.write_all(response).await {
+        Ok(()) => Ok(true),
+        Err(err) if net::is_closed_error(&err) => {
+            trace!(target: "lazymc", "Client closed connection while writing response");
+            Ok(false)
+        }
+        Err(err) => {
+            warn!(target: "lazymc", "Failed to write response to client: {}", err);
+            Err(())
+        }
+    }
+}
+
+/// Best-effort decode of a slightly malformed handshake, such as with an overlong server address.
+///
+/// Returns `None` if unrecoverable.
+fn recover_handshake(data: &[u8]) -> Option<Handshake> {
+    let (read, protocol_version) = types::read_var_int(data).ok()?;
+    let data = &data[read..];
+
+    // Read server address leniently, ignoring length limit and invalid characters
+    let (read, addr_len) = types::read_var_int(data).ok()?;
+    let data = &data[read..];
+    let addr_len = usize::try_from(addr_len).ok()?;
+    if data.len() < addr_len + 3 {
+        return None;
+    }
+    let server_addr = String::from_utf8_lossy(&data[..addr_len]).into_owned();
+    let data = &data[addr_len..];
+
+    let server_port = u16::from_be_bytes([data[0], data[1]]);
+    let (_, next_state) = types::read_var_int(&data[2..]).ok()?;
+
+    debug!(target: "lazymc", "Recovered malformed handshake from client");
+
+    Some(Handshake {
+        protocol_version,
+        server_addr,
+        server_port,
+        next_state,
+    })
+}
+
+/// Check whether packet data received in login state is a repeated handshake.
+///
+/// A login start has the same packet ID, but never decodes as handshake with the protocol version
+/// of the client.
+fn is_repeated_handshake(client_info: &ClientInfo, data: &[u8]) -> bool {
+    match Handshake::decode(&mut &data[..]) {
+        Ok(handshake) => {
+            Some(handshake.protocol_version as u32) == client_info.protocol()
+                && ClientState::from_id(handshake.next_state).is_some()
+        }
+        Err(_) => false,
+    }
+}
+
+/// Whether an idle connection in the given state may be dropped when the server starts.
+///
+/// Only status connections without pending data are dropped, such as lingering status requests,
+/// never clients that are handshaking or logging in.
+fn is_droppable(config: &Config, state: ClientState, buf: &[u8]) -> bool {
+    config.advanced.drop_idle_on_start && state == ClientState::Status && buf.is_empty()
+}
+
+/// Wait until the server is starting, and the client has been idle for a while.
+///
+/// Never returns if the server doesn't start.
+async fn idle_while_starting(state: &mut watch::Receiver<server::State>, last_read: Instant) {
+    while *state.borrow() != server::State::Starting {
+        if state.changed().await.is_err() {
+            future::pending::<()>().await;
+        }
+    }
+
+    time::sleep_until((last_read + DROP_IDLE_AFTER).into()).await;
+}
+
+/// Fetch live status from server, by relaying the client handshake and status request.
+///
+/// Returns the raw status response packet to relay to the client.
+async fn fetch_live_status(
+    config: &Config,
+    server: &Server,
+    handshake: &[u8],
+    request: &[u8],
+) -> Result<Vec<u8>, ()> {
+    let task = async {
+        let mut stream = net::connect(
+            &server.poll_addresses(config),
+            config.server.connect_timeout(),
+        )
+        .await
+        .map_err(|_| ())?;
+
+        // Add proxy header
+        if config.server.send_proxy_v2 {
+            stream
+                .write_all(&proxy::local_proxy_header().map_err(|_| ())?)
+                .await
+                .map_err(|_| ())?;
+        }
+
+        // Relay handshake and status request
+        stream.write_all(handshake).await.map_err(|_| ())?;
+        stream.write_all(request).await.map_err(|_| ())?;
+
+        // Wait for status response
+        let client = Client::dummy();
+        let (mut reader, _) = stream.split();
+        let mut buf = BytesMut::new();
+        loop {
+            match packet::read_packet(&client, &mut buf, &mut reader).await {
+                Ok(Some((packet, raw))) if packet.id == packets::status::CLIENT_STATUS => {
+                    return Ok(raw)
+                }
+                Ok(Some(_)) => continue,
+                Ok(None) | Err(()) => return Err(()),
+            }
+        }
+    };
+
+    time::timeout(LIVE_STATUS_TIMEOUT, task)
+        .await
+        .map_err(|_| ())?
+}
+
+/// Build player sample entry with the given name and nil UUID.
+fn sample_player(name: &str) -> OnlinePlayer {
+    serde_json::from_value(serde_json::json!({ "name": name, "id": NIL_UUID }))
+        .expect("failed to build player sample entry")
+}
+
 /// Build server status object to respond to client with.
 async fn server_status(client_info: &ClientInfo, config: &Config, server: &Server) -> ServerStatus {
     let status = server.status().await;
     let server_state = server.state();
 
-    // Respond with real server status if started
+    // Respond with real server status if started, use empty MOTD and favicon without players
+    // Cached server status already fits maximum size, fit again if changed
     if server_state == server::State::Started && status.is_some() {
-        return status.as_ref().unwrap().clone();
+        let mut status = status.as_ref().unwrap().clone();
+        if status.players.online == 0 {
+            if let Some(ref motd) = config.motd.empty {
+                status.description =
+                    motd_message(&render_motd(motd, server).await, config, client_info);
+            }
+            if favicon::supports_favicon(client_info) {
+                if let Some(favicon) = empty_favicon(config).await {
+                    status.favicon = Some(favicon);
+                }
+            }
+            if config.motd.has_empty() {
+                server.status_fit.fit(&mut status, config);
+            }
+        }
+        return status;
     }
 
     // Select version and player max from last known server status
@@ -227,40 +821,280 @@ async fn server_status(client_info: &ClientInfo, config: &Config, server: &Serve
         ),
     };
 
+    // Select theme active today
+    let theme = config.motd.theme(Local::now().date_naive());
+
     // Select description, use server MOTD if enabled, or use configured
     let description = {
         if config.motd.from_server && status.is_some() {
             status.as_ref().unwrap().description.clone()
         } else {
-            Message::new(Payload::text(match server_state {
-                server::State::Stopped | server::State::Started => &config.motd.sleeping,
-                server::State::Starting => &config.motd.starting,
-                server::State::Stopping => &config.motd.stopping,
-            }))
+            // Use frozen MOTD if set and server process is frozen
+            let frozen = match config.motd.frozen {
+                Some(ref frozen) if server.is_frozen().await => Some(frozen),
+                _ => None,
+            };
+            let motd = match (server_state, &config.motd.from_server_fallback, frozen) {
+                // No server MOTD known yet while sleeping, use fallback if set
+                (server::State::Stopped | server::State::Started, Some(fallback), _)
+                    if config.motd.from_server =>
+                {
+                    fallback
+                }
+                (server::State::Stopped, _, Some(frozen)) => frozen,
+                (server::State::Stopped | server::State::Started, _, _) => theme
+                    .and_then(|t| t.sleeping.as_ref())
+                    .unwrap_or(&config.motd.sleeping),
+                (server::State::Starting, _, _) => theme
+                    .and_then(|t| t.starting.as_ref())
+                    .unwrap_or(&config.motd.starting),
+                (server::State::Stopping, _, _) => theme
+                    .and_then(|t| t.stopping.as_ref())
+                    .unwrap_or(&config.motd.stopping),
+            };
+            motd_message(&render_motd(motd, server).await, config, client_info)
         }
     };
 
-    // Extract favicon from real server status, load from disk, or use default
+    // Extract favicon from real server status, use theme, load from disk, or use default
     let mut favicon = None;
     if favicon::supports_favicon(client_info) {
         if config.motd.from_server && status.is_some() {
-            favicon = status.as_ref().unwrap().favicon.clone()
+            favicon = status.as_ref().unwrap().favicon.clone();
+        }
+        if let (None, Some(path)) = (&favicon, theme.and_then(|t| t.favicon.as_ref())) {
+            favicon = load_favicon(config, path).await;
         }
         if favicon.is_none() {
             favicon = Some(server_favicon(config).await);
         }
     }
 
-    // Build status resposne
-    ServerStatus {
+    // Show rotating player sample entry while starting
+    let sample = match server_state {
+        server::State::Starting => server
+            .next_starting_sample(config)
+            .map(|name| vec![sample_player(name)])
+            .unwrap_or_default(),
+        _ => vec![],
+    };
+
+    // Build status resposne, fit to maximum size
+    let mut status = ServerStatus {
         version,
         description,
         players: OnlinePlayers {
             online: 0,
             max,
-            sample: vec![],
+            sample,
         },
         favicon,
+    };
+    server.status_fit.fit(&mut status, config);
+    status
+}
+
+/// Render MOTD, replacing placeholders with current values.
+///
+/// Supports `{uptime}` and `{asleep_for}`, showing `unknown` if not known.
+async fn render_motd(motd: &str, server: &Server) -> String {
+    let mut motd = motd.to_string();
+
+    if motd.contains(MOTD_UPTIME) {
+        let uptime = server.uptime().await;
+        motd = motd.replace(MOTD_UPTIME, &format_placeholder_duration(uptime));
+    }
+    if motd.contains(MOTD_ASLEEP_FOR) {
+        let asleep_for = server.asleep_for().await;
+        motd = motd.replace(MOTD_ASLEEP_FOR, &format_placeholder_duration(asleep_for));
+    }
+
+    motd
+}
+
+/// Build chat message from MOTD in configured format.
+///
+/// Falls back to plain text if MiniMessage parsing fails. Hex colors are only used if the client
+/// supports them.
+fn motd_message(motd: &str, config: &Config, client_info: &ClientInfo) -> Message {
+    match config.motd.format {
+        MotdFormat::Legacy => Message::new(Payload::text(motd)),
+        MotdFormat::MiniMessage => {
+            let hex = minimessage::supports_hex_colors(client_info);
+            minimessage::parse(motd, hex).unwrap_or_else(|| {
+                debug!(target: "lazymc", "Failed to parse MOTD as MiniMessage, using plain text");
+                Message::new(Payload::text(motd))
+            })
+        }
+        MotdFormat::Plain => Message::new(Payload::text(&strip_formatting_codes(motd))),
+    }
+}
+
+/// Remove legacy `§` formatting codes from text.
+fn strip_formatting_codes(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '§' {
+            chars.next();
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Format duration for MOTD placeholder.
+fn format_placeholder_duration(duration: Option<Duration>) -> String {
+    match duration {
+        Some(duration) => format_duration(duration),
+        None => MOTD_UNKNOWN.into(),
+    }
+}
+
+/// How a status response was shrunk to fit the maximum size.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct StatusFit {
+    /// Whether the player sample was dropped.
+    dropped_sample: bool,
+
+    /// Whether the favicon was dropped.
+    dropped_favicon: bool,
+
+    /// Whether it still exceeds the maximum size.
+    oversized: bool,
+}
+
+impl StatusFit {
+    /// Whether the status response fit as is.
+    pub fn fits(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Drop the same parts from the given status response.
+    fn apply(&self, status: &mut ServerStatus) {
+        if self.dropped_sample {
+            status.players.sample.clear();
+        }
+        if self.dropped_favicon {
+            status.favicon = None;
+        }
+    }
+
+    /// Warn about how the status response was shrunk.
+    pub fn warn(&self, config: &Config) {
+        let max = config.motd.max_status_size;
+        if self.dropped_sample {
+            warn!(target: "lazymc::status", "Status response exceeds maximum size of {}, dropping player sample", max);
+        }
+        if self.dropped_favicon {
+            warn!(target: "lazymc::status", "Status response exceeds maximum size of {}, dropping favicon", max);
+        }
+        if self.oversized {
+            warn!(target: "lazymc::status", "Status response still exceeds maximum size of {}, MOTD may be too long", max);
+        }
+    }
+}
+
+/// Cache of how status responses built by lazymc are shrunk to fit the maximum size.
+///
+/// Only recomputed if the MOTD, favicon or player sample changes, warns once for each change in
+/// how it is shrunk.
+#[derive(Debug, Default)]
+pub struct StatusFitCache {
+    /// Last fit, with its key.
+    last: Mutex<Option<(FitKey, StatusFit)>>,
+}
+
+/// Key of status fit cache: description JSON, favicon size and player sample size.
+type FitKey = (String, usize, usize);
+
+impl StatusFitCache {
+    /// Shrink status response to fit configured maximum size.
+    pub fn fit(&self, status: &mut ServerStatus, config: &Config) {
+        if config.motd.max_status_size == 0 {
+            return;
+        }
+
+        let key = (
+            serde_json::to_string(&status.description).unwrap_or_default(),
+            status.favicon.as_ref().map(|f| f.len()).unwrap_or(0),
+            status.players.sample.iter().map(|p| p.name.len() + 1).sum(),
+        );
+        let mut last = self.last.lock().unwrap();
+        match last.as_ref() {
+            Some((last_key, fit)) if *last_key == key => fit.apply(status),
+            _ => {
+                let fit = fit_status_size(status, config);
+                if !fit.fits() && last.as_ref().map(|(_, last)| *last) != Some(fit) {
+                    fit.warn(config);
+                }
+                *last = Some((key, fit));
+            }
+        }
+    }
+}
+
+/// Shrink status response to fit configured maximum size.
+///
+/// Drops the player sample first, then the favicon. Oversized responses may break some clients.
+/// Returns how it was shrunk, does not log.
+pub fn fit_status_size(status: &mut ServerStatus, config: &Config) -> StatusFit {
+    let mut fit = StatusFit::default();
+    let max = config.motd.max_status_size as usize;
+    if max == 0 || status_size(status) <= max {
+        return fit;
+    }
+
+    if !status.players.sample.is_empty() {
+        status.players.sample.clear();
+        fit.dropped_sample = true;
+        if status_size(status) <= max {
+            return fit;
+        }
+    }
+
+    if status.favicon.is_some() {
+        status.favicon = None;
+        fit.dropped_favicon = true;
+        if status_size(status) <= max {
+            return fit;
+        }
+    }
+
+    fit.oversized = true;
+    fit
+}
+
+/// Get size of status response JSON, in UTF-16 code units as limited by clients.
+fn status_size(status: &ServerStatus) -> usize {
+    serde_json::to_string(status)
+        .map(|json| json.encode_utf16().count())
+        .unwrap_or(0)
+}
+
+/// Get server status favicon for when the server is online without players.
+///
+/// Returns `None` if not configured or if it fails to load.
+async fn empty_favicon(config: &Config) -> Option<String> {
+    load_favicon(config, config.motd.favicon_empty.as_ref()?).await
+}
+
+/// Load favicon from given path, relative to the server directory.
+///
+/// Returns `None` if it fails to load.
+async fn load_favicon(config: &Config, path: &Path) -> Option<String> {
+    let path = match ConfigServer::server_directory(config) {
+        Some(dir) => dir.join(path),
+        None => path.to_path_buf(),
+    };
+
+    match fs::read(&path).await {
+        Ok(data) => Some(favicon::encode_favicon(&data)),
+        Err(err) => {
+            error!(target: "lazymc::status", "Failed to load favicon from {}: {}", path.display(), err);
+            None
+        }
     }
 }
 
@@ -293,3 +1127,636 @@ async fn server_favicon(config: &Config) -> String {
 
     favicon::encode_favicon(&data)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    /// Timeout for test clients waiting on a response.
+    const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Build config, with the given TOML appended to a minimal server section.
+    fn config(toml: &str) -> Config {
+        toml::from_str(&format!("[server]\ncommand = \"true\"\n{toml}")).unwrap()
+    }
+
+    /// Encode raw packet with length prefix.
+    fn packet(id: u8, data: &[u8]) -> Vec<u8> {
+        RawPacket::new(id, data.to_vec())
+            .encode_with_len(&Client::dummy())
+            .unwrap()
+    }
+
+    /// Encode handshake packet data.
+    fn handshake_data(protocol: i32, addr: &str, next_state: i32) -> Vec<u8> {
+        let mut data = types::encode_var_int(protocol).unwrap();
+        data.extend(string(addr));
+        data.extend(25565u16.to_be_bytes());
+        data.extend(types::encode_var_int(next_state).unwrap());
+        data
+    }
+
+    /// Encode handshake packet.
+    fn handshake(protocol: i32, next_state: i32) -> Vec<u8> {
+        packet(
+            packets::handshake::SERVER_HANDSHAKE,
+            &handshake_data(protocol, "localhost", next_state),
+        )
+    }
+
+    /// Encode string with length prefix.
+    fn string(text: &str) -> Vec<u8> {
+        let mut data = types::encode_var_int(text.len() as i32).unwrap();
+        data.extend(text.as_bytes());
+        data
+    }
+
+    /// Encode login start packet, as sent by 1.20.2 (protocol 764) and newer clients.
+    fn login_start(name: &str) -> Vec<u8> {
+        let mut data = string(name);
+        data.extend([0; 16]);
+        packet(packets::login::SERVER_LOGIN_START, &data)
+    }
+
+    /// Encode login start packet, as sent by clients older than 1.19 (protocol 759).
+    fn legacy_login_start(name: &str) -> Vec<u8> {
+        packet(packets::login::SERVER_LOGIN_START, &string(name))
+    }
+
+    /// Get reason of login disconnect packet, as raw JSON.
+    fn disconnect_reason(packet: &RawPacket) -> String {
+        let (read, len) = types::read_var_int(&packet.data).unwrap();
+        String::from_utf8(packet.data[read..read + len as usize].to_vec()).unwrap()
+    }
+
+    /// Build server status, as reported by an online server.
+    fn online_status(players: u32) -> ServerStatus {
+        ServerStatus {
+            version: ServerVersion {
+                name: "1.20.4".into(),
+                protocol: 765,
+            },
+            description: Message::new(Payload::text("A Minecraft Server")),
+            players: OnlinePlayers {
+                online: players,
+                max: 20,
+                sample: vec![],
+            },
+            favicon: None,
+        }
+    }
+
+    /// Test client connected to `serve`.
+    struct TestClient {
+        stream: TcpStream,
+        buf: BytesMut,
+    }
+
+    impl TestClient {
+        /// Serve a new client connection with the given config and server.
+        async fn connect(config: Config, server: Arc<Server>) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let addr = listener.local_addr().unwrap();
+            let config = Arc::new(config);
+            tokio::spawn(async move {
+                let (inbound, peer) = listener.accept().await.unwrap();
+                let _ = serve(Client::new(peer), inbound, config, server).await;
+            });
+
+            Self {
+                stream: TcpStream::connect(addr).await.unwrap(),
+                buf: BytesMut::new(),
+            }
+        }
+
+        /// Send raw data, ignoring errors if the connection is closed already.
+        async fn send(&mut self, data: &[u8]) {
+            let _ = self.stream.write_all(data).await;
+        }
+
+        /// Receive next packet, `None` if the connection is closed.
+        async fn recv(&mut self) -> Option<RawPacket> {
+            let (mut reader, _) = self.stream.split();
+            let read = packet::read_packet(&Client::dummy(), &mut self.buf, &mut reader);
+            time::timeout(READ_TIMEOUT, read)
+                .await
+                .expect("timed out waiting for response")
+                .ok()
+                .flatten()
+                .map(|(packet, _)| packet)
+        }
+    }
+
+    #[tokio::test]
+    async fn status_request_before_handshake() {
+        let server = Arc::new(Server::default());
+        let mut client = TestClient::connect(config(""), server.clone()).await;
+
+        client
+            .send(&packet(packets::status::SERVER_STATUS, &[]))
+            .await;
+        assert!(client.recv().await.is_none());
+        assert_eq!(server.metrics.handshake_errors.snapshot().total, 1);
+    }
+
+    #[tokio::test]
+    async fn login_throttled_while_started() {
+        let config = config("[join]\nmax_logins_per_minute = 1");
+        let server = Arc::new(Server::default());
+        server.update_status(&config, Some(online_status(1))).await;
+        assert_eq!(server.state(), server::State::Started);
+        assert!(server.try_login(&config).await);
+
+        let mut client = TestClient::connect(config, server).await;
+        client.send(&handshake(765, 2)).await;
+        client.send(&login_start("player")).await;
+        let packet = client.recv().await.unwrap();
+        assert!(disconnect_reason(&packet).contains("Too many players are logging in"));
+    }
+
+    #[test]
+    fn drop_idle_only_status() {
+        let config = config("[advanced]\ndrop_idle_on_start = true");
+        assert!(is_droppable(&config, ClientState::Status, &[]));
+        assert!(!is_droppable(&config, ClientState::Status, &[1]));
+        assert!(!is_droppable(&config, ClientState::Handshake, &[]));
+        assert!(!is_droppable(&config, ClientState::Login, &[]));
+
+        let config = self::config("");
+        assert!(!is_droppable(&config, ClientState::Status, &[]));
+    }
+
+    #[tokio::test]
+    async fn live_status_relayed_while_started() {
+        let live = packet(packets::status::CLIENT_STATUS, &string("{\"live\":true}"));
+
+        // Fake server, responding to status request with live status
+        let backend = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = backend.local_addr().unwrap();
+        tokio::spawn({
+            let live = live.clone();
+            async move {
+                let (mut stream, _) = backend.accept().await.unwrap();
+                let (mut reader, mut writer) = stream.split();
+                let mut buf = BytesMut::new();
+                let client = Client::dummy();
+                let (handshake, _) = packet::read_packet(&client, &mut buf, &mut reader)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(handshake.id, packets::handshake::SERVER_HANDSHAKE);
+                let (request, _) = packet::read_packet(&client, &mut buf, &mut reader)
+                    .await
+                    .unwrap()
+                    .unwrap();
+                assert_eq!(request.id, packets::status::SERVER_STATUS);
+                writer.write_all(&live).await.unwrap();
+                let _ = packet::read_packet(&client, &mut buf, &mut reader).await;
+            }
+        });
+
+        let config = config(&format!(
+            "address = \"{addr}\"\n[motd]\nproxy_when_running = true"
+        ));
+        let server = Arc::new(Server::default());
+        server.update_status(&config, Some(online_status(1))).await;
+        assert_eq!(server.state(), server::State::Started);
+
+        let mut client = TestClient::connect(config, server).await;
+        client.send(&handshake(765, 1)).await;
+        client
+            .send(&packet(packets::status::SERVER_STATUS, &[]))
+            .await;
+        let response = client.recv().await.unwrap();
+        assert_eq!(response.id, packets::status::CLIENT_STATUS);
+        assert_eq!(response.data, string("{\"live\":true}"));
+    }
+
+    #[tokio::test]
+    async fn handshake_protocol_zero_dropped() {
+        let server = Arc::new(Server::default());
+        let mut client = TestClient::connect(config(""), server.clone()).await;
+
+        client.send(&handshake(0, 1)).await;
+        client
+            .send(&packet(packets::status::SERVER_STATUS, &[]))
+            .await;
+        assert!(client.recv().await.is_none());
+        let errors = server.metrics.handshake_errors.snapshot();
+        assert_eq!(errors.total, 1);
+        assert_eq!(errors.by_protocol.get(&Some(0)), Some(&1));
+    }
+
+    #[tokio::test]
+    async fn truncated_handshake_dropped() {
+        let server = Arc::new(Server::default());
+        let mut client = TestClient::connect(config(""), server.clone()).await;
+
+        // Server address is cut off
+        let mut data = types::encode_var_int(765).unwrap();
+        data.extend(types::encode_var_int(9).unwrap());
+        data.extend(b"local");
+        client
+            .send(&packet(packets::handshake::SERVER_HANDSHAKE, &data))
+            .await;
+        assert!(client.recv().await.is_none());
+        let errors = server.metrics.handshake_errors.snapshot();
+        assert_eq!(errors.total, 1);
+        assert_eq!(errors.by_protocol.get(&Some(765)), Some(&1));
+    }
+
+    #[test]
+    fn recover_valid_handshake() {
+        // Server address longer than the allowed 255 characters
+        let addr = "a".repeat(300);
+        let handshake = recover_handshake(&handshake_data(765, &addr, 2)).unwrap();
+        assert_eq!(handshake.protocol_version, 765);
+        assert_eq!(handshake.server_addr, addr);
+        assert_eq!(handshake.server_port, 25565);
+        assert_eq!(handshake.next_state, 2);
+    }
+
+    #[test]
+    fn recover_truncated_handshake() {
+        let data = handshake_data(765, &"a".repeat(300), 2);
+        for len in 0..data.len() {
+            assert!(recover_handshake(&data[..len]).is_none(), "length {len}");
+        }
+    }
+
+    #[test]
+    fn recover_garbage_handshake() {
+        assert!(recover_handshake(&[0xFF; 16]).is_none());
+
+        // Negative server address length
+        let mut data = types::encode_var_int(765).unwrap();
+        data.extend(types::encode_var_int(-1).unwrap());
+        data.extend([0; 8]);
+        assert!(recover_handshake(&data).is_none());
+    }
+
+    #[tokio::test]
+    async fn legacy_client_status() {
+        let config = config("[motd]\nformat = \"minimessage\"\nsleeping = \"<#ff0000>Sleeping\"");
+        let server = Arc::new(Server::default());
+        let mut client = TestClient::connect(config, server).await;
+
+        // 1.8 client, hex colors are not supported
+        client.send(&handshake(47, 1)).await;
+        client
+            .send(&packet(packets::status::SERVER_STATUS, &[]))
+            .await;
+        let response = client.recv().await.unwrap();
+        assert_eq!(response.id, packets::status::CLIENT_STATUS);
+        let status: serde_json::Value =
+            serde_json::from_str(&disconnect_reason(&response)).unwrap();
+        let description = status["description"].to_string();
+        assert!(description.contains("dark_red"), "{description}");
+        assert!(!description.contains("#ff0000"), "{description}");
+        assert!(status["version"]["protocol"].is_number());
+
+        // Ping is echoed as is
+        let ping = packet(packets::status::SERVER_PING, &1234567890i64.to_be_bytes());
+        client.send(&ping).await;
+        let pong = client.recv().await.unwrap();
+        assert_eq!(pong.id, packets::status::SERVER_PING);
+        assert_eq!(pong.data, 1234567890i64.to_be_bytes());
+    }
+
+    #[tokio::test]
+    async fn legacy_client_login() {
+        let config = config("[lockout]\nenabled = true\nmessage = \"Closed\"");
+        let server = Arc::new(Server::default());
+        let mut client = TestClient::connect(config, server.clone()).await;
+
+        // 1.8 client, login start only has username
+        client.send(&handshake(47, 2)).await;
+        client.send(&legacy_login_start("player")).await;
+        let packet = client.recv().await.unwrap();
+        assert_eq!(packet.id, packets::login::CLIENT_DISCONNECT);
+        assert!(disconnect_reason(&packet).contains("Closed"));
+        assert_eq!(server.metrics.login_start_errors.snapshot().total, 0);
+    }
+
+    #[tokio::test]
+    async fn malformed_login_start_continues() {
+        let config = config("[lockout]\nenabled = true\nmessage = \"Closed\"");
+        let server = Arc::new(Server::default());
+        let mut client = TestClient::connect(config, server.clone()).await;
+
+        // Username length exceeds packet, login continues without username
+        client.send(&handshake(765, 2)).await;
+        client
+            .send(&packet(packets::login::SERVER_LOGIN_START, &[0x10, b'a']))
+            .await;
+        let packet = client.recv().await.unwrap();
+        assert_eq!(packet.id, packets::login::CLIENT_DISCONNECT);
+        assert!(disconnect_reason(&packet).contains("Closed"));
+        assert_eq!(server.metrics.login_start_errors.snapshot().total, 1);
+    }
+
+    #[tokio::test]
+    async fn repeated_handshake_dropped() {
+        for next_state in [1, 2] {
+            let server = Arc::new(Server::default());
+            let mut client = TestClient::connect(config(""), server.clone()).await;
+
+            client.send(&handshake(765, next_state)).await;
+            client.send(&handshake(765, next_state)).await;
+            assert!(client.recv().await.is_none());
+            let errors = server.metrics.handshake_errors.snapshot();
+            assert_eq!(errors.total, 1);
+            assert_eq!(errors.by_protocol.get(&Some(765)), Some(&1));
+            assert_eq!(server.state(), server::State::Stopped);
+        }
+    }
+
+    #[tokio::test]
+    async fn repeated_status_request_served() {
+        let server = Arc::new(Server::default());
+        let mut client = TestClient::connect(config(""), server).await;
+
+        client.send(&handshake(765, 1)).await;
+        for _ in 0..2 {
+            client
+                .send(&packet(packets::status::SERVER_STATUS, &[]))
+                .await;
+            let response = client.recv().await.unwrap();
+            assert_eq!(response.id, packets::status::CLIENT_STATUS);
+        }
+    }
+
+    #[tokio::test]
+    async fn status_request_in_login_dropped() {
+        let server = Arc::new(Server::default());
+        let mut client = TestClient::connect(config(""), server.clone()).await;
+
+        client.send(&handshake(765, 2)).await;
+        client
+            .send(&packet(packets::status::SERVER_STATUS, &[]))
+            .await;
+        assert!(client.recv().await.is_none());
+        assert_eq!(server.state(), server::State::Stopped);
+        assert_eq!(server.metrics.login_start_errors.snapshot().total, 0);
+    }
+
+    #[tokio::test]
+    async fn silent_client_dropped() {
+        let config = config("[advanced]\nidle_connect_timeout = 1");
+        let server = Arc::new(Server::default());
+        let mut client = TestClient::connect(config, server).await;
+
+        // Client connects and never sends anything
+        let start = Instant::now();
+        assert!(client.recv().await.is_none());
+        assert!(start.elapsed() >= Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn known_protocol_status() {
+        let config = config("[public]\nunknown_protocol_behavior = \"disconnect\"");
+        let server = Arc::new(Server::default());
+        let mut client = TestClient::connect(config, server).await;
+
+        client.send(&handshake(765, 1)).await;
+        client
+            .send(&packet(packets::status::SERVER_STATUS, &[]))
+            .await;
+        let response = client.recv().await.unwrap();
+        assert_eq!(response.id, packets::status::CLIENT_STATUS);
+    }
+
+    #[tokio::test]
+    async fn unknown_protocol_status() {
+        // Respond with configured version
+        let config = self::config("[public]\nunknown_protocol_behavior = \"configured\"");
+        let protocol = config.public.protocol;
+        let server = Arc::new(Server::default());
+        let mut client = TestClient::connect(config, server).await;
+
+        client.send(&handshake(-1, 1)).await;
+        client
+            .send(&packet(packets::status::SERVER_STATUS, &[]))
+            .await;
+        let response = client.recv().await.unwrap();
+        assert_eq!(response.id, packets::status::CLIENT_STATUS);
+        let status: serde_json::Value =
+            serde_json::from_str(&disconnect_reason(&response)).unwrap();
+        assert_eq!(status["version"]["protocol"], protocol);
+
+        // Disconnect
+        let config = self::config("[public]\nunknown_protocol_behavior = \"disconnect\"");
+        let server = Arc::new(Server::default());
+        let mut client = TestClient::connect(config, server).await;
+
+        client.send(&handshake(-1, 1)).await;
+        client
+            .send(&packet(packets::status::SERVER_STATUS, &[]))
+            .await;
+        assert!(client.recv().await.is_none());
+    }
+
+    #[tokio::test]
+    async fn consistent_login_allowed() {
+        let toml = "[join]\nstrict_protocol_consistency = true\n[lockout]\nenabled = true\nmessage = \"Closed\"";
+        for (protocol, login_start) in [
+            (765, login_start("player")),
+            (47, legacy_login_start("player")),
+        ] {
+            let server = Arc::new(Server::default());
+            let mut client = TestClient::connect(config(toml), server.clone()).await;
+
+            // Passes consistency check, kicked by lockout
+            client.send(&handshake(protocol, 2)).await;
+            client.send(&login_start).await;
+            let packet = client.recv().await.unwrap();
+            assert!(
+                disconnect_reason(&packet).contains("Closed"),
+                "protocol {protocol}"
+            );
+            assert_eq!(server.metrics.login_start_errors.snapshot().total, 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn inconsistent_login_kicked() {
+        let toml = "[join]\nstrict_protocol_consistency = true";
+        for (protocol, login_start) in [
+            (765, legacy_login_start("player")),
+            (47, login_start("player")),
+        ] {
+            let server = Arc::new(Server::default());
+            let mut client = TestClient::connect(config(toml), server.clone()).await;
+
+            client.send(&handshake(protocol, 2)).await;
+            client.send(&login_start).await;
+            let packet = client.recv().await.unwrap();
+            assert!(
+                disconnect_reason(&packet).contains("does not match its protocol version"),
+                "protocol {protocol}"
+            );
+            assert_eq!(server.metrics.login_start_errors.snapshot().total, 1);
+            assert_eq!(server.state(), server::State::Stopped);
+        }
+    }
+
+    #[tokio::test]
+    async fn unadopted_external_not_started() {
+        let config = Arc::new(config("adopt_external = false"));
+        let server = Arc::new(Server::default());
+        server.update_status(&config, Some(online_status(0))).await;
+        assert_eq!(server.state(), server::State::Stopped);
+        assert!(server.is_unadopted_external(&config));
+        assert!(!Server::start(config.clone(), server.clone(), None).await);
+
+        // Joining player is kicked instead of starting a second server
+        let mut client =
+            TestClient::connect(self::config("adopt_external = false"), server.clone()).await;
+        client.send(&handshake(765, 2)).await;
+        client.send(&login_start("player")).await;
+        let packet = client.recv().await.unwrap();
+        assert!(disconnect_reason(&packet).contains("running outside of lazymc"));
+        assert_eq!(server.state(), server::State::Stopped);
+
+        // Server started outside of lazymc stopped, may be started again
+        server.update_status(&config, None).await;
+        assert!(!server.is_unadopted_external(&config));
+    }
+
+    #[tokio::test]
+    async fn write_response_client_closed() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap())
+            .await
+            .unwrap();
+        let (mut inbound, _) = listener.accept().await.unwrap();
+        let (_, mut writer) = inbound.split();
+        assert_eq!(write_response(&mut writer, &[0; 16]).await, Ok(true));
+
+        // Client closes, writing eventually fails with closed error rather than other error
+        drop(client);
+        let data = vec![0; 64 * 1024];
+        let mut closed = false;
+        for _ in 0..100 {
+            match write_response(&mut writer, &data).await {
+                Ok(true) => time::sleep(Duration::from_millis(10)).await,
+                Ok(false) => {
+                    closed = true;
+                    break;
+                }
+                Err(()) => panic!("closed connection reported as write error"),
+            }
+        }
+        assert!(closed);
+    }
+
+    #[tokio::test]
+    async fn from_server_fallback_only_sleeping() {
+        let config = config(
+            "[motd]\nfrom_server = true\nfrom_server_fallback = \"Waiting for data\"\nstarting = \"Starting up\"\nstopping = \"Stopping now\"",
+        );
+        let server = Server::default();
+        let client_info = ClientInfo::empty();
+        let description =
+            |status: ServerStatus| serde_json::to_string(&status.description).unwrap();
+
+        let status = server_status(&client_info, &config, &server).await;
+        assert!(description(status).contains("Waiting for data"));
+
+        server.set_state(server::State::Starting, &config).await;
+        let status = server_status(&client_info, &config, &server).await;
+        assert!(description(status).contains("Starting up"));
+
+        server.set_state(server::State::Stopping, &config).await;
+        let status = server_status(&client_info, &config, &server).await;
+        assert!(description(status).contains("Stopping now"));
+    }
+
+    #[test]
+    fn status_size_counts_utf16() {
+        let mut status = online_status(0);
+        status.description = Message::new(Payload::text("aa"));
+        let ascii = status_size(&status);
+
+        // Emoji outside the BMP take two UTF-16 code units each
+        status.description = Message::new(Payload::text("\u{1F600}"));
+        assert_eq!(status_size(&status), ascii);
+        let json = serde_json::to_string(&status).unwrap();
+        assert!(json.chars().count() < status_size(&status));
+    }
+
+    #[test]
+    fn oversized_status_dropped_in_order() {
+        let mut status = online_status(3);
+        status.players.sample = (0..3)
+            .map(|i| sample_player(&format!("player{i}")))
+            .collect();
+        status.favicon = Some(format!("data:image/png;base64,{}", "A".repeat(200)));
+
+        let mut bare = status.clone();
+        bare.players.sample.clear();
+        let with_favicon = status_size(&bare);
+        bare.favicon = None;
+        let without = status_size(&bare);
+
+        // Only the sample needs to go
+        let mut fitted = status.clone();
+        let fit = fit_status_size(
+            &mut fitted,
+            &config(&format!("[motd]\nmax_status_size = {with_favicon}")),
+        );
+        assert!(fit.dropped_sample && !fit.dropped_favicon && !fit.oversized);
+        assert!(fitted.players.sample.is_empty());
+        assert!(fitted.favicon.is_some());
+
+        // Sample and favicon must go
+        let mut fitted = status.clone();
+        let fit = fit_status_size(
+            &mut fitted,
+            &config(&format!("[motd]\nmax_status_size = {without}")),
+        );
+        assert!(fit.dropped_sample && fit.dropped_favicon && !fit.oversized);
+        assert!(fitted.players.sample.is_empty());
+        assert!(fitted.favicon.is_none());
+
+        // Still too large, description is kept as is
+        let mut fitted = status.clone();
+        let fit = fit_status_size(&mut fitted, &config("[motd]\nmax_status_size = 1"));
+        assert!(fit.oversized);
+        assert_eq!(status_size(&fitted), without);
+
+        // Unlimited
+        let mut fitted = status.clone();
+        let fit = fit_status_size(&mut fitted, &config("[motd]\nmax_status_size = 0"));
+        assert!(fit.fits());
+        assert_eq!(fitted.players.sample.len(), 3);
+    }
+
+    #[test]
+    fn status_fit_cached_by_motd() {
+        let mut status = online_status(0);
+        status.favicon = Some(format!("data:image/png;base64,{}", "A".repeat(200)));
+        let config = config(&format!(
+            "[motd]\nmax_status_size = {}",
+            status_size(&status) - 1
+        ));
+        let cache = StatusFitCache::default();
+        let last_fit = || cache.last.lock().unwrap().as_ref().unwrap().1;
+
+        // Favicon is dropped, and again from cache
+        for _ in 0..2 {
+            let mut fitted = status.clone();
+            cache.fit(&mut fitted, &config);
+            assert!(fitted.favicon.is_none());
+        }
+        assert!(last_fit().dropped_favicon);
+
+        // Recomputed for changed MOTD, shorter one fits
+        let mut fitted = status.clone();
+        fitted.description = Message::new(Payload::text(""));
+        cache.fit(&mut fitted, &config);
+        assert!(fitted.favicon.is_some());
+        assert!(last_fit().fits());
+    }
+}
@@ -2,6 +2,7 @@ pub mod cli;
 pub mod error;
 pub mod serde;
 pub mod style;
+pub mod time;
 
 use std::env;
 use std::path::PathBuf;
@@ -1,3 +1,4 @@
+use std::io;
 use std::net::{SocketAddr, ToSocketAddrs};
 
 use serde::de::{Error, Unexpected};
@@ -28,3 +29,89 @@ where
         Error::invalid_value(Unexpected::Str(&addr), &"IP or resolvable host and port")
     })
 }
+
+/// Deserialize a host and port into all socket addresses it resolves to.
+///
+/// Addresses are in resolved order, never empty.
+pub fn to_all_socket_addrs<'de, D>(d: D) -> Result<Vec<SocketAddr>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let addr = String::deserialize(d)?;
+    resolve_all(&addr, |addr| {
+        addr.to_socket_addrs().map(|addrs| addrs.collect())
+    })
+    .ok_or_else(|| Error::invalid_value(Unexpected::Str(&addr), &"IP or resolvable host and port"))
+}
+
+/// Resolve all unique socket addresses of the given address through the given resolver.
+///
+/// Falls back to parsing a raw IP address. Returns `None` if nothing could be resolved.
+fn resolve_all<R>(addr: &str, resolve: R) -> Option<Vec<SocketAddr>>
+where
+    R: Fn(&str) -> io::Result<Vec<SocketAddr>>,
+{
+    let mut addrs = vec![];
+    for resolved in resolve(addr).unwrap_or_default() {
+        if !addrs.contains(&resolved) {
+            addrs.push(resolved);
+        }
+    }
+
+    if addrs.is_empty() {
+        addrs.extend(addr.parse::<SocketAddr>().ok());
+    }
+
+    (!addrs.is_empty()).then_some(addrs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Mock resolver returning a fixed list of addresses.
+    fn mock_resolver(
+        addrs: &'static [&'static str],
+    ) -> impl Fn(&str) -> io::Result<Vec<SocketAddr>> {
+        move |_| Ok(addrs.iter().map(|addr| addr.parse().unwrap()).collect())
+    }
+
+    #[test]
+    fn resolve_all_multiple() {
+        let addrs = resolve_all(
+            "mc.example.com:25566",
+            mock_resolver(&["10.0.0.1:25566", "10.0.0.2:25566", "[fd00::1]:25566"]),
+        );
+        assert_eq!(
+            addrs.unwrap(),
+            vec![
+                "10.0.0.1:25566".parse::<SocketAddr>().unwrap(),
+                "10.0.0.2:25566".parse().unwrap(),
+                "[fd00::1]:25566".parse().unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn resolve_all_dedup() {
+        let addrs = resolve_all(
+            "mc.example.com:25566",
+            mock_resolver(&["10.0.0.1:25566", "10.0.0.2:25566", "10.0.0.1:25566"]),
+        );
+        assert_eq!(addrs.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn resolve_all_fallback_raw_ip() {
+        let failing = |_: &str| Err(io::Error::new(io::ErrorKind::Other, "no resolver"));
+        assert_eq!(
+            resolve_all("127.0.0.1:25566", failing),
+            Some(vec!["127.0.0.1:25566".parse().unwrap()])
+        );
+        assert_eq!(resolve_all("mc.example.com:25566", failing), None);
+        assert_eq!(
+            resolve_all("mc.example.com:25566", mock_resolver(&[])),
+            None
+        );
+    }
+}
@@ -0,0 +1,16 @@
+use std::time::Duration;
+
+/// Format duration in a short human readable form, such as `2h 15m`.
+///
+/// Shows the two most significant units. Durations below a minute are shown in seconds.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (days, hours, mins) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+
+    match (days, hours, mins) {
+        (0, 0, 0) => format!("{secs}s"),
+        (0, 0, mins) => format!("{mins}m"),
+        (0, hours, mins) => format!("{hours}h {mins}m"),
+        (days, hours, _) => format!("{days}d {hours}h"),
+    }
+}